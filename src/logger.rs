@@ -0,0 +1,69 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::{task, todo};
+
+const LOG_FILE: &str = "todot.log";
+
+struct FileLogger {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{} {} {}", task::now(), record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+pub fn init(verbose: bool, log_level: Option<String>) {
+    let level = match (verbose, log_level) {
+        (true, _) => LevelFilter::Debug,
+        (false, Some(log_level)) => LevelFilter::from_str(&log_level).unwrap_or(LevelFilter::Off),
+        (false, None) => LevelFilter::Off,
+    };
+
+    if level == LevelFilter::Off {
+        return;
+    }
+
+    let file = match todo::get_data_dir().and_then(|path| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.join(LOG_FILE))
+    }) {
+        Ok(file) => file,
+        Err(_) => return,
+    };
+
+    let logger = FileLogger {
+        file: Mutex::new(file),
+        level,
+    };
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
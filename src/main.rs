@@ -1,54 +1,205 @@
+mod logger;
+mod storage;
 mod task;
 mod todo;
 
 use std::{
     error::Error,
-    fs,
-    io::{self, Stdout},
-    path::PathBuf,
-    time::Duration,
+    io::{self, Stdout, Write},
+    time::Instant,
 };
 
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
-    terminal::{disable_raw_mode, enable_raw_mode, LeaveAlternateScreen},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode, LeaveAlternateScreen, SetTitle},
 };
 use crossterm::{execute, terminal::EnterAlternateScreen};
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::Text,
-    widgets::{Block, Borders, List, Paragraph},
+    widgets::{BarChart, Block, BorderType, Borders, Clear, List, ListState, Paragraph, Wrap},
     Terminal,
 };
-use todo::Todo;
+use task::TaskKind;
+use todo::{BorderStyle, Options, Todo};
+
+const PUSH_TITLE: &str = "\x1b[22;0t";
+const POP_TITLE: &str = "\x1b[23;0t";
+const MIN_TWO_PANE_WIDTH: u16 = 80;
+const TAG_SIDEBAR_WIDTH: u16 = 20;
 
 #[derive(PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Insert,
     Delete,
+    RecentlyDone,
+    Estimate,
+    Reload,
+    Palette,
+    Split,
+    Histogram,
+}
+
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn main() -> Result<(), io::Error> {
-    let mut todo = match Todo::load() {
-        Ok(todo) => todo,
-        Err(_) => Todo::new(),
+fn try_main() -> Result<(), io::Error> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let verbose = args.iter().any(|arg| arg == "--verbose");
+    args.retain(|arg| arg != "--verbose");
+
+    logger::init(verbose, todo::get_options().log_level);
+
+    let command = args.first().map(String::as_str);
+    let mut todo = Todo::new();
+
+    if command.is_none_or(|command| mutates_database(command, &args[1..])) {
+        todo.acquire_lock();
+    } else {
+        todo.read_only = true;
+    }
+
+    if todo.read_only && command.is_some_and(|command| only_appends(command, &args[1..])) {
+        log::info!("Appending a task while another instance holds the lock");
+        todo.read_only = false;
+    }
+
+    let load_error = match todo.load_tasks() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log::info!("No tasks saved yet, starting empty");
+            None
+        }
+        Err(e) => {
+            log::error!("Unable to load tasks: {}", e);
+            todo.read_only = true;
+            todo.notice = Some(format!(
+                "Unable to load tasks, changes will not be saved: {}",
+                e
+            ));
+            Some(e)
+        }
+        Ok(()) => None,
     };
 
+    if let Some(command) = command {
+        if let Some(e) = load_error.filter(|_| mutates_database(command, &args[1..])) {
+            return Err(e);
+        }
+
+        if let Some(notice) = todo.notice.take() {
+            eprintln!("Warning: {}", notice);
+        }
+
+        if todo.read_only && mutates_database(command, &args[1..]) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Another taskt instance is running, close it and try again",
+            ));
+        }
+
+        let result = run_command(command, &args[1..], &mut todo);
+        todo.release_lock();
+
+        return result;
+    }
+
+    todo.restore_ui_state();
+
     let mut terminal = setup_terminal().unwrap();
 
     run(&mut terminal, &mut todo)?;
 
     restore_terminal(&mut terminal).unwrap();
 
+    if let Err(e) = todo.save_ui_state() {
+        log::warn!("Unable to save UI state: {}", e);
+    }
+
+    todo.release_lock();
+
+    if let Err(e) = todo.backup_to_server() {
+        eprintln!("Warning: unable to back up tasks to server: {}", e);
+    }
+
     Ok(())
 }
 
+fn mutates_database(command: &str, args: &[String]) -> bool {
+    match command {
+        "export-jsonl" | "count" => false,
+        "template" => args.first().map(String::as_str) == Some("apply"),
+        _ => true,
+    }
+}
+
+fn only_appends(command: &str, args: &[String]) -> bool {
+    match command {
+        "capture" => true,
+        "export-jsonl" | "count" | "template" => false,
+        text => args.is_empty() && !text.starts_with('-'),
+    }
+}
+
+fn run_command(command: &str, args: &[String], todo: &mut Todo) -> Result<(), io::Error> {
+    match command {
+        "export-jsonl" => todo.export_jsonl(io::BufWriter::new(io::stdout().lock())),
+        "template" => match (args.first().map(String::as_str), args.get(1)) {
+            (Some("save"), Some(name)) => todo.save_template(name),
+            (Some("apply"), Some(name)) => todo.apply_template(name),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Usage: taskt template <save|apply> <name>",
+            )),
+        },
+        "count" => {
+            let filters = todo::parse_filters(args)?;
+
+            println!("{}", todo.count_matching(&filters));
+            Ok(())
+        }
+        "capture" => {
+            let mut stdout = io::stdout();
+            stdout.write_all(b"New task: ")?;
+            stdout.flush()?;
+
+            let mut text = String::new();
+            io::stdin().read_line(&mut text)?;
+
+            match text.trim() {
+                "" => Ok(()),
+                text => add_task(todo, text),
+            }
+        }
+        text if args.is_empty() && !text.starts_with('-') => add_task(todo, text),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown command: {}", command),
+        )),
+    }
+}
+
+fn add_task(todo: &mut Todo, text: &str) -> Result<(), io::Error> {
+    match todo.fit_to_max_length(todo.autocapitalize(text.to_string())) {
+        Some(text) => todo.add(text),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Task is longer than max_task_length",
+        )),
+    }
+}
+
 fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, Box<dyn Error>> {
     let mut stdout = io::stdout();
     enable_raw_mode()?;
+    stdout.write_all(PUSH_TITLE.as_bytes())?;
     execute!(stdout, EnterAlternateScreen)?;
     Ok(Terminal::new(CrosstermBackend::new(stdout))?)
 }
@@ -58,6 +209,8 @@ fn restore_terminal(
 ) -> Result<(), Box<dyn Error>> {
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+    terminal.backend_mut().write_all(POP_TITLE.as_bytes())?;
+    terminal.backend_mut().flush()?;
     Ok(terminal.show_cursor()?)
 }
 
@@ -65,9 +218,30 @@ fn run(
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
     todo: &mut Todo,
 ) -> Result<(), io::Error> {
+    let mut title = String::new();
+
     loop {
+        todo.refresh_lock();
+        todo.reload_if_changed();
+        todo.autosave_if_idle();
+
+        if todo.mode == Mode::Normal {
+            todo.archive_completed_if_due();
+            todo.sync_with_server_if_due();
+        }
+
+        if todo.title() != title {
+            title = todo.title();
+            execute!(terminal.backend_mut(), SetTitle(&title))?;
+        }
+
         draw(terminal, todo).unwrap();
 
+        if std::mem::take(&mut todo.ring_bell) {
+            terminal.backend_mut().write_all(b"\x07")?;
+            terminal.backend_mut().flush()?;
+        }
+
         match handle_input(todo) {
             Ok(()) => {}
             Err(e) => {
@@ -86,51 +260,301 @@ fn draw(
 ) -> Result<(), io::Error> {
     terminal.draw(|f| {
         let size = f.size();
+        let hint_height = match todo.options.show_hints {
+            true => 1,
+            false => 0,
+        };
+
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+            .constraints(
+                [
+                    Constraint::Min(0),
+                    Constraint::Length(3),
+                    Constraint::Length(hint_height),
+                ]
+                .as_ref(),
+            )
             .split(size);
 
-        let tasks =
-            List::new(todo.list()).block(Block::default().title("Tasks").borders(Borders::ALL));
+        let two_pane = todo.show_detail_pane
+            && size.width >= MIN_TWO_PANE_WIDTH
+            && matches!(todo.mode, Mode::Normal | Mode::Delete | Mode::Reload);
+
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(match two_pane {
+                true => [Constraint::Percentage(60), Constraint::Percentage(40)],
+                false => [Constraint::Percentage(100), Constraint::Percentage(0)],
+            })
+            .split(layout[0]);
+
+        let tag_counts = todo.tag_counts();
+        let show_sidebar = todo.show_tag_sidebar
+            && !tag_counts.is_empty()
+            && matches!(todo.mode, Mode::Normal | Mode::Delete | Mode::Reload);
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(match show_sidebar {
+                true => [Constraint::Length(TAG_SIDEBAR_WIDTH), Constraint::Min(0)],
+                false => [Constraint::Length(0), Constraint::Min(0)],
+            })
+            .split(panes[0]);
+
+        let tasks_area = block(&todo.options, String::new()).inner(columns[1]);
+        let input_area = block(&todo.options, String::new()).inner(layout[1]);
+
+        let mut tasks_title = todo
+            .options
+            .tasks_title
+            .clone()
+            .unwrap_or_else(|| String::from("Tasks"));
+
+        if todo.read_only {
+            tasks_title.push_str(" (read-only)");
+        }
+        let mut input_title = todo
+            .options
+            .input_title
+            .clone()
+            .unwrap_or_else(|| String::from("Add a task"));
+
+        if let Some(sync_status) = todo.sync_status() {
+            input_title = format!("{} ({})", input_title, sync_status);
+        }
+
+        let split_text = todo.new_task_text.replace('\n', " | ");
+
+        let tasks = match todo.mode {
+            Mode::RecentlyDone => List::new(todo.list_recently_completed())
+                .block(block(&todo.options, String::from("Recently completed"))),
+            Mode::Palette => List::new(todo.list_palette_matches())
+                .block(block(&todo.options, String::from("Jump to task"))),
+            _ => List::new(todo.list()).block(block(&todo.options, tasks_title)),
+        };
 
         let new_task_text = match todo.mode {
-            Mode::Normal => "Add a task (Press 'i' to insert)",
+            Mode::Normal => match &todo.notice {
+                Some(notice) => notice,
+                None => "Add a task (Press 'i' to add, 'o'/'O' to insert below/above)",
+            },
             Mode::Delete => "Press 'd' again to delete the selected task",
-            Mode::Insert => &todo.new_task_text,
+            Mode::Reload => "Unsynced changes will be lost. Press 'R' again to reload anyway",
+            Mode::RecentlyDone => "Press space to un-complete, 'r' or Esc to go back",
+            Mode::Histogram => "Press 'H' or Esc to go back",
+            Mode::Insert | Mode::Estimate | Mode::Palette => &todo.new_task_text,
+            Mode::Split => &split_text,
+        };
+
+        let too_long = todo.mode == Mode::Insert && todo.exceeds_max_length(new_task_text);
+
+        let new_task_title = match todo.options.max_task_length {
+            Some(max) if too_long => format!(
+                "{} ({}/{} characters, {})",
+                input_title,
+                new_task_text.chars().count(),
+                max,
+                if todo.options.truncate_long_tasks {
+                    "will be truncated"
+                } else {
+                    "too long"
+                }
+            ),
+            _ if todo.mode == Mode::Estimate => {
+                String::from("Estimate in minutes (empty to clear)")
+            }
+            _ if todo.mode == Mode::Palette => {
+                String::from("Search tasks (Enter to jump, Esc to cancel)")
+            }
+            _ if todo.mode == Mode::Split => {
+                String::from("Split task (Alt+Enter for a new line, Enter to split)")
+            }
+            _ => input_title,
         };
 
         let new_task = Paragraph::new(Text::raw(new_task_text))
             .style(match todo.mode {
-                Mode::Normal => Style::default(),
-                Mode::Delete => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-                Mode::Insert => Style::default().add_modifier(Modifier::BOLD),
+                Mode::Normal | Mode::RecentlyDone | Mode::Histogram => Style::default(),
+                Mode::Delete | Mode::Reload => todo
+                    .fg(Style::default(), Color::Red)
+                    .add_modifier(Modifier::BOLD),
+                Mode::Insert if too_long => todo
+                    .fg(Style::default(), Color::Red)
+                    .add_modifier(Modifier::BOLD),
+                Mode::Insert | Mode::Estimate | Mode::Palette | Mode::Split => {
+                    Style::default().add_modifier(Modifier::BOLD)
+                }
             })
-            .block(Block::default().title("Add a task").borders(Borders::ALL));
+            .block(block(&todo.options, new_task_title));
 
-        if todo.mode == Mode::Insert {
-            f.set_cursor(
-                layout[1].x + new_task_text.len() as u16 + 1,
-                layout[1].y + 1,
-            );
+        if todo.mode == Mode::Insert
+            || todo.mode == Mode::Estimate
+            || todo.mode == Mode::Palette
+            || todo.mode == Mode::Split
+        {
+            f.set_cursor(input_area.x + new_task_text.len() as u16, input_area.y);
+        }
+
+        let mut tasks_state = ListState::default();
+        tasks_state.select(match todo.mode {
+            Mode::RecentlyDone => Some(todo.recent_task),
+            Mode::Palette => Some(todo.palette_task),
+            _ => todo.selected_position(),
+        });
+
+        match todo.mode {
+            Mode::Histogram => {
+                let completions = todo.completions_per_day();
+                let data: Vec<(&str, u64)> = completions
+                    .iter()
+                    .map(|(label, count)| (label.as_str(), *count))
+                    .collect();
+
+                let chart = BarChart::default()
+                    .block(block(
+                        &todo.options,
+                        String::from("Completed in the last 7 days"),
+                    ))
+                    .data(&data)
+                    .bar_width(5)
+                    .bar_gap(1)
+                    .bar_style(todo.fg(Style::default(), Color::Green))
+                    .value_style(Style::default().add_modifier(Modifier::REVERSED));
+
+                f.render_widget(chart, layout[0]);
+            }
+            _ => f.render_stateful_widget(tasks, columns[1], &mut tasks_state),
         }
 
-        f.render_widget(tasks, layout[0]);
+        if show_sidebar {
+            let tags =
+                List::new(todo.list_tag_counts()).block(block(&todo.options, String::from("Tags")));
+
+            f.render_widget(tags, columns[0]);
+        }
+
+        if two_pane {
+            let details = Paragraph::new(todo.detail_lines().join("\n"))
+                .wrap(Wrap { trim: false })
+                .block(block(&todo.options, String::from("Details")));
+
+            f.render_widget(details, panes[1]);
+        }
         f.render_widget(new_task, layout[1]);
+
+        if todo.options.show_hints {
+            let hint = hint(&todo.mode);
+            let width = layout[2].width as usize;
+
+            let hint = match hint.chars().count() > width {
+                true => format!(
+                    "{}…",
+                    hint.chars()
+                        .take(width.saturating_sub(1))
+                        .collect::<String>()
+                ),
+                false => hint.to_string(),
+            };
+
+            f.render_widget(
+                Paragraph::new(hint).style(Style::default().add_modifier(Modifier::DIM)),
+                layout[2],
+            );
+        }
+
+        let placeholder = match todo.mode {
+            Mode::RecentlyDone | Mode::Palette | Mode::Histogram => None,
+            _ => todo.placeholder(),
+        };
+
+        if let Some(placeholder) = placeholder {
+            let area = Rect::new(
+                tasks_area.x,
+                tasks_area.y + tasks_area.height / 2,
+                tasks_area.width,
+                1.min(tasks_area.height),
+            );
+
+            f.render_widget(
+                Paragraph::new(placeholder)
+                    .alignment(Alignment::Center)
+                    .style(Style::default().add_modifier(Modifier::DIM)),
+                area,
+            );
+        }
+
+        if todo.show_legend {
+            let legend = todo.legend();
+
+            let width = 20.min(tasks_area.width);
+            let height = (legend.len() as u16 + 2).min(tasks_area.height);
+            let area = Rect::new(
+                tasks_area.x + tasks_area.width - width,
+                tasks_area.y + tasks_area.height - height,
+                width,
+                height,
+            );
+
+            f.render_widget(Clear, area);
+            f.render_widget(
+                List::new(legend).block(Block::default().title("Legend").borders(Borders::ALL)),
+                area,
+            );
+        }
     })?;
 
     Ok(())
 }
 
+fn hint(mode: &Mode) -> &'static str {
+    match mode {
+        Mode::Normal => {
+            "i: add  space: toggle  dd: delete  e: estimate  s: split  :: jump  q: quit"
+        }
+        Mode::Insert => "Enter: save  Esc: cancel  Ctrl+R: restore discarded",
+        Mode::Delete => "d: delete  Esc: cancel",
+        Mode::RecentlyDone => "space: un-complete  j/k: move  Esc: back",
+        Mode::Estimate => "Enter: save  empty: clear  Esc: cancel",
+        Mode::Reload => "R: reload  Esc: cancel",
+        Mode::Palette => "Enter: jump  Up/Down: move  Esc: cancel",
+        Mode::Split => "Alt+Enter: new line  Enter: split  Esc: cancel",
+        Mode::Histogram => "H/Esc: back",
+    }
+}
+
+fn block<'a>(options: &Options, title: String) -> Block<'a> {
+    let block = Block::default().title(title);
+
+    match options.border_style {
+        BorderStyle::None => block,
+        BorderStyle::Plain => block.borders(Borders::ALL),
+        BorderStyle::Rounded => block.borders(Borders::ALL).border_type(BorderType::Rounded),
+    }
+}
+
 fn handle_input(todo: &mut Todo) -> Result<(), Box<dyn Error>> {
-    if event::poll(Duration::from_millis(250))? {
+    if event::poll(todo.options.poll_interval())? {
         if let Event::Key(key) = event::read()? {
             if key.kind != KeyEventKind::Press {
                 return Ok(());
             }
 
+            todo.last_input = Instant::now();
+
+            if is_interrupt(&key) {
+                todo.save()?;
+                return Err("Quitting".into());
+            }
+
+            let pending_key = todo.pending_key.take();
+            todo.notice = None;
+
             match key.code {
                 _ if todo.mode == Mode::Insert => handle_insert_mode(key, todo),
+                _ if todo.mode == Mode::Estimate => handle_estimate_mode(key, todo),
+                _ if todo.mode == Mode::Palette => handle_palette_mode(key, todo),
+                _ if todo.mode == Mode::Split => handle_split_mode(key, todo),
                 _ if todo.mode == Mode::Delete => match key.code {
                     KeyCode::Char('d') => {
                         todo.delete();
@@ -141,8 +565,56 @@ fn handle_input(todo: &mut Todo) -> Result<(), Box<dyn Error>> {
                     }
                     _ => {}
                 },
-                KeyCode::Char('i') | KeyCode::Char('o') | KeyCode::Char('a') => {
+                _ if todo.mode == Mode::Reload => match key.code {
+                    KeyCode::Char('R') => {
+                        todo.reload();
+                        todo.mode = Mode::Normal;
+                    }
+                    KeyCode::Esc => {
+                        todo.mode = Mode::Normal;
+                    }
+                    _ => {}
+                },
+                _ if todo.mode == Mode::Histogram => match key.code {
+                    KeyCode::Char('H') | KeyCode::Esc => {
+                        todo.mode = Mode::Normal;
+                    }
+                    _ => {}
+                },
+                _ if todo.mode == Mode::RecentlyDone => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => todo.prev_recent(),
+                    KeyCode::Down | KeyCode::Char('j') => todo.next_recent(),
+                    KeyCode::Char(' ') | KeyCode::Enter => todo.toggle_recent(),
+                    KeyCode::Char('r') | KeyCode::Esc => {
+                        todo.mode = Mode::Normal;
+                    }
+                    _ => {}
+                },
+                KeyCode::Char('i') | KeyCode::Char('a') => {
                     todo.new_task_text = String::new();
+                    todo.insert_index = None;
+                    todo.mode = Mode::Insert;
+                }
+                KeyCode::Char('o') => {
+                    todo.new_task_text = String::new();
+                    todo.insert_index = Some(todo.neighbour_index(true));
+                    todo.mode = Mode::Insert;
+                }
+                KeyCode::Char('f') if !todo.tasks.is_empty() => {
+                    todo.new_task_text = String::new();
+                    todo.insert_index = Some(todo.neighbour_index(true));
+                    todo.complete();
+                    todo.mode = Mode::Insert;
+                }
+                KeyCode::Char('-') => {
+                    todo.new_task_text = String::new();
+                    todo.insert_index = Some(todo.neighbour_index(true));
+                    todo.insert_kind = TaskKind::Divider;
+                    todo.mode = Mode::Insert;
+                }
+                KeyCode::Char('O') => {
+                    todo.new_task_text = String::new();
+                    todo.insert_index = Some(todo.neighbour_index(false));
                     todo.mode = Mode::Insert;
                 }
                 KeyCode::Char('q') => {
@@ -150,14 +622,67 @@ fn handle_input(todo: &mut Todo) -> Result<(), Box<dyn Error>> {
                 }
                 KeyCode::Up | KeyCode::Char('k') => todo.prev(),
                 KeyCode::Down | KeyCode::Char('j') => todo.next(),
+                KeyCode::Home => todo.first(),
+                KeyCode::End | KeyCode::Char('G') => todo.last(),
+                KeyCode::Char('g') if pending_key == Some('g') => todo.first(),
+                KeyCode::Char('x') if pending_key == Some('g') => todo.open_url(),
+                KeyCode::Char('g') => {
+                    todo.pending_key = Some('g');
+                }
                 KeyCode::Char(' ') | KeyCode::Enter => {
                     todo.toggle();
                 }
-                KeyCode::Char('d') => {
-                    if todo.mode == Mode::Normal {
-                        todo.mode = Mode::Delete;
+                KeyCode::Char('d') if todo.options.quick_delete => todo.delete(),
+                KeyCode::Char('d') if todo.mode == Mode::Normal => {
+                    todo.mode = Mode::Delete;
+                }
+                KeyCode::Char('s') => {
+                    if let Some(task) = todo.tasks.get(todo.current_task) {
+                        if !task.is_divider() {
+                            todo.new_task_text = task.text.clone();
+                            todo.mode = Mode::Split;
+                        }
                     }
                 }
+                KeyCode::Char('e') if !todo.tasks.is_empty() => {
+                    todo.new_task_text = todo
+                        .tasks
+                        .get(todo.current_task)
+                        .and_then(|task| task.estimate_minutes)
+                        .map(|estimate| estimate.to_string())
+                        .unwrap_or_default();
+                    todo.mode = Mode::Estimate;
+                }
+                KeyCode::Char('t') => todo.toggle_timer(),
+                KeyCode::Char('w') => todo.cycle_status(),
+                KeyCode::Tab => todo.indent(),
+                KeyCode::BackTab => todo.outdent(),
+                KeyCode::Char('D') => todo.dedup(),
+                KeyCode::Char('c') => todo.toggle_completed_collapsed(),
+                KeyCode::Char('v') => todo.toggle_view_density(),
+                KeyCode::Char('N') => todo.toggle_display_reversed(),
+                KeyCode::Char('L') => todo.toggle_legend(),
+                KeyCode::Char('P') => todo.toggle_detail_pane(),
+                KeyCode::Char('T') => todo.toggle_tag_sidebar(),
+                KeyCode::Char('#') => todo.cycle_tag_filter(),
+                KeyCode::Char('z') => todo.toggle_subtasks(),
+                KeyCode::Char('X') => todo.toggle_visible(),
+                KeyCode::Char('H') => {
+                    todo.mode = Mode::Histogram;
+                }
+                KeyCode::Char('R') if todo.has_unsynced_changes() => {
+                    todo.mode = Mode::Reload;
+                }
+                KeyCode::Char('R') => todo.reload(),
+                KeyCode::Char(':') => {
+                    todo.new_task_text = String::new();
+                    todo.palette_task = 0;
+                    todo.mode = Mode::Palette;
+                }
+                KeyCode::Char('r') => {
+                    todo.recent_task = 0;
+                    todo.mode = Mode::RecentlyDone;
+                }
                 _ => {}
             }
         }
@@ -166,17 +691,130 @@ fn handle_input(todo: &mut Todo) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn is_interrupt(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
 fn handle_insert_mode(key: KeyEvent, todo: &mut Todo) {
+    match key.code {
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            if let Some(text) = todo.last_discarded_input.take() {
+                todo.new_task_text = text;
+            }
+        }
+        KeyCode::Char(c) => {
+            todo.new_task_text.push(c);
+        }
+        KeyCode::Backspace => {
+            todo.new_task_text.pop();
+        }
+        KeyCode::Enter => {
+            let text = todo.autocapitalize(todo.new_task_text.clone());
+
+            if let Some(text) = todo.fit_to_max_length(text) {
+                match todo.insert_index.take() {
+                    Some(index) => {
+                        todo.insert_at(index, text);
+
+                        if todo.options.rapid_insert {
+                            todo.insert_index = Some(todo.neighbour_index(true));
+                        }
+                    }
+                    None => todo.insert(text),
+                }
+
+                if !todo.options.rapid_insert {
+                    todo.mode = Mode::Normal;
+                }
+                todo.new_task_text = String::new();
+            }
+        }
+        KeyCode::Esc => {
+            let text = std::mem::take(&mut todo.new_task_text);
+
+            if todo.options.remember_discarded_input && !text.is_empty() {
+                todo.last_discarded_input = Some(text);
+            }
+
+            todo.mode = Mode::Normal;
+            todo.insert_kind = TaskKind::Task;
+        }
+        _ => {}
+    }
+}
+
+fn handle_estimate_mode(key: KeyEvent, todo: &mut Todo) {
+    match key.code {
+        KeyCode::Char(c) if c.is_ascii_digit() => {
+            todo.new_task_text.push(c);
+        }
+        KeyCode::Backspace => {
+            todo.new_task_text.pop();
+        }
+        KeyCode::Enter => {
+            if let Ok(estimate) = todo.new_task_text.parse::<u32>() {
+                todo.set_estimate(Some(estimate));
+            } else if todo.new_task_text.is_empty() {
+                todo.set_estimate(None);
+            }
+            todo.mode = Mode::Normal;
+            todo.new_task_text = String::new();
+        }
+        KeyCode::Esc => {
+            todo.mode = Mode::Normal;
+            todo.new_task_text = String::new();
+        }
+        _ => {}
+    }
+}
+
+fn handle_split_mode(key: KeyEvent, todo: &mut Todo) {
+    match key.code {
+        KeyCode::Char(c) => {
+            todo.new_task_text.push(c);
+        }
+        KeyCode::Backspace => {
+            todo.new_task_text.pop();
+        }
+        KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+            todo.new_task_text.push('\n');
+        }
+        KeyCode::Enter => {
+            let lines: Option<Vec<String>> = todo
+                .new_task_text
+                .lines()
+                .map(|line| todo.fit_to_max_length(todo.autocapitalize(line.to_string())))
+                .collect();
+
+            if let Some(lines) = lines {
+                todo.split(todo.current_task, lines);
+                todo.mode = Mode::Normal;
+                todo.new_task_text = String::new();
+            }
+        }
+        KeyCode::Esc => {
+            todo.mode = Mode::Normal;
+            todo.new_task_text = String::new();
+        }
+        _ => {}
+    }
+}
+
+fn handle_palette_mode(key: KeyEvent, todo: &mut Todo) {
     match key.code {
         KeyCode::Char(c) => {
             todo.new_task_text.push(c);
+            todo.clamp_palette_selection();
         }
         KeyCode::Backspace => {
             todo.new_task_text.pop();
+            todo.clamp_palette_selection();
         }
+        KeyCode::Up => todo.prev_palette(),
+        KeyCode::Down => todo.next_palette(),
         KeyCode::Enter => {
+            todo.jump_to_palette_selection();
             todo.mode = Mode::Normal;
-            todo.insert(todo.new_task_text.clone());
             todo.new_task_text = String::new();
         }
         KeyCode::Esc => {
@@ -187,9 +825,36 @@ fn handle_insert_mode(key: KeyEvent, todo: &mut Todo) {
     }
 }
 
-fn ensure_dir_exists(path: &PathBuf) -> Result<(), Box<dyn Error>> {
-    if !path.exists() {
-        fs::create_dir_all(path)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_match_each_mode() {
+        for (mode, expected) in [
+            (
+                Mode::Normal,
+                "i: add  space: toggle  dd: delete  e: estimate  s: split  :: jump  q: quit",
+            ),
+            (
+                Mode::Insert,
+                "Enter: save  Esc: cancel  Ctrl+R: restore discarded",
+            ),
+            (Mode::Delete, "d: delete  Esc: cancel"),
+            (
+                Mode::RecentlyDone,
+                "space: un-complete  j/k: move  Esc: back",
+            ),
+            (Mode::Estimate, "Enter: save  empty: clear  Esc: cancel"),
+            (Mode::Reload, "R: reload  Esc: cancel"),
+            (Mode::Palette, "Enter: jump  Up/Down: move  Esc: cancel"),
+            (
+                Mode::Split,
+                "Alt+Enter: new line  Enter: split  Esc: cancel",
+            ),
+            (Mode::Histogram, "H/Esc: back"),
+        ] {
+            assert_eq!(hint(&mode), expected);
+        }
     }
-    Ok(())
 }
@@ -0,0 +1,97 @@
+use std::io::{Error, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+use crate::task::Task;
+
+pub trait Storage {
+    fn file_name(&self) -> &'static str;
+    fn serialize(&self, tasks: &[Task]) -> Result<String, Error>;
+    fn deserialize(&self, data: &str) -> Result<Vec<Task>, Error>;
+}
+
+pub struct JsonStorage;
+impl Storage for JsonStorage {
+    fn file_name(&self) -> &'static str {
+        "db.json"
+    }
+
+    fn serialize(&self, tasks: &[Task]) -> Result<String, Error> {
+        Ok(serde_json::to_string(tasks)?)
+    }
+
+    fn deserialize(&self, data: &str) -> Result<Vec<Task>, Error> {
+        Ok(serde_json::from_str(data)?)
+    }
+}
+
+pub struct YamlStorage;
+impl Storage for YamlStorage {
+    fn file_name(&self) -> &'static str {
+        "db.yaml"
+    }
+
+    fn serialize(&self, tasks: &[Task]) -> Result<String, Error> {
+        serde_yaml::to_string(tasks).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn deserialize(&self, data: &str) -> Result<Vec<Task>, Error> {
+        serde_yaml::from_str(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+impl StorageFormat {
+    pub const ALL: [StorageFormat; 2] = [StorageFormat::Json, StorageFormat::Yaml];
+
+    pub fn storage(self) -> &'static dyn Storage {
+        match self {
+            StorageFormat::Json => &JsonStorage,
+            StorageFormat::Yaml => &YamlStorage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskStatus;
+
+    #[test]
+    fn round_trips_tasks_in_every_format() {
+        let mut done = Task::new(String::from("buy milk"));
+        done.set_status(TaskStatus::Done);
+        done.indent_level = 1;
+
+        let tasks = vec![Task::new(String::from("write: \"report\"")), done];
+
+        for format in StorageFormat::ALL {
+            let storage = format.storage();
+            let data = storage.serialize(&tasks).unwrap();
+            let loaded = storage.deserialize(&data).unwrap();
+
+            assert_eq!(loaded.len(), 2);
+            assert_eq!(loaded[0].text, "write: \"report\"");
+            assert!(!loaded[0].is_done());
+            assert_eq!(loaded[1].text, "buy milk");
+            assert!(loaded[1].is_done());
+            assert_eq!(loaded[1].completed_at, tasks[1].completed_at);
+            assert_eq!(loaded[1].indent_level, 1);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        for format in StorageFormat::ALL {
+            let error = format.storage().deserialize("- [").err().unwrap();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
@@ -1,15 +1,401 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use serde::{Deserialize, Serialize};
 
+const MAX_INDENT_LEVEL: u8 = 4;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    #[default]
+    Todo,
+    Waiting,
+    Done,
+}
+impl TaskStatus {
+    pub fn next(self) -> Self {
+        match self {
+            TaskStatus::Todo => TaskStatus::Waiting,
+            TaskStatus::Waiting => TaskStatus::Done,
+            TaskStatus::Done => TaskStatus::Todo,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskKind {
+    #[default]
+    Task,
+    Divider,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "StoredTask")]
 pub struct Task {
     pub text: String,
-    pub completed: bool,
+    pub kind: TaskKind,
+    pub status: TaskStatus,
+    pub completed_at: Option<u64>,
+    pub estimate_minutes: Option<u32>,
+    pub spent_seconds: u64,
+    pub timer_started_at: Option<u64>,
+    pub indent_level: u8,
+    pub collapsed: bool,
 }
 impl Task {
     pub fn new(text: String) -> Self {
         Self {
             text,
-            completed: false,
+            kind: TaskKind::Task,
+            status: TaskStatus::Todo,
+            completed_at: None,
+            estimate_minutes: None,
+            spent_seconds: 0,
+            timer_started_at: None,
+            indent_level: 0,
+            collapsed: false,
+        }
+    }
+
+    pub fn fresh_copy(&self) -> Self {
+        Self {
+            kind: self.kind,
+            estimate_minutes: self.estimate_minutes,
+            indent_level: self.indent_level,
+            ..Self::new(self.text.clone())
+        }
+    }
+
+    pub fn normalize_whitespace(&mut self) {
+        self.text = self.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+
+        for word in self.text.split_whitespace() {
+            if let Some(tag) = word
+                .trim_end_matches(|c: char| c.is_ascii_punctuation())
+                .strip_prefix('#')
+            {
+                let tag = tag.to_lowercase();
+
+                if !tag.is_empty() && !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.to_lowercase();
+
+        self.tags().contains(&tag)
+    }
+
+    pub fn is_divider(&self) -> bool {
+        self.kind == TaskKind::Divider
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status == TaskStatus::Done
+    }
+
+    pub fn set_completed(&mut self, completed: bool) {
+        self.set_status(match completed {
+            true => TaskStatus::Done,
+            false => TaskStatus::Todo,
+        });
+    }
+
+    pub fn set_status(&mut self, status: TaskStatus) {
+        if self.is_divider() {
+            return;
+        }
+
+        if status == TaskStatus::Done {
+            self.stop_timer();
+        }
+
+        self.completed_at = match (status, self.status) {
+            (TaskStatus::Done, TaskStatus::Done) => self.completed_at,
+            (TaskStatus::Done, _) => Some(now()),
+            _ => None,
+        };
+        self.status = status;
+    }
+
+    pub fn indent(&mut self) {
+        self.indent_level = (self.indent_level + 1).min(MAX_INDENT_LEVEL);
+    }
+
+    pub fn outdent(&mut self) {
+        self.indent_level = self.indent_level.saturating_sub(1);
+    }
+
+    pub fn indentation(&self) -> String {
+        "  ".repeat(self.indent_level as usize)
+    }
+
+    pub fn start_timer(&mut self) {
+        if self.timer_started_at.is_none() && !self.is_divider() {
+            self.timer_started_at = Some(now());
+        }
+    }
+
+    pub fn stop_timer(&mut self) {
+        self.stop_timer_at(now());
+    }
+
+    fn stop_timer_at(&mut self, stopped_at: u64) {
+        if let Some(started_at) = self.timer_started_at.take() {
+            self.spent_seconds += stopped_at.saturating_sub(started_at);
+        }
+    }
+
+    pub fn toggle_timer(&mut self) {
+        match self.timer_started_at {
+            Some(_) => self.stop_timer(),
+            None => self.start_timer(),
+        }
+    }
+
+    pub fn total_spent_minutes(&self) -> u64 {
+        self.spent_minutes_at(now())
+    }
+
+    fn spent_minutes_at(&self, now: u64) -> u64 {
+        let running = self
+            .timer_started_at
+            .map_or(0, |started_at| now.saturating_sub(started_at));
+
+        (self.spent_seconds + running + 30) / 60
+    }
+
+    pub fn details(&self) -> Vec<String> {
+        let mut details = Vec::new();
+
+        if let Some(estimate) = self.estimate_minutes {
+            details.push(format!("estimate {}m", estimate));
+        }
+
+        if self.total_spent_minutes() > 0 || self.timer_started_at.is_some() {
+            details.push(format!("spent {}m", self.total_spent_minutes()));
+        }
+
+        if let Some(started_at) = self.timer_started_at {
+            details.push(format!("timer started {}", format_age(started_at)));
+        }
+
+        if let Some(completed_at) = self.completed_at {
+            details.push(format!("completed {}", format_age(completed_at)));
+        }
+
+        details
+    }
+
+    pub fn effort(&self) -> Option<String> {
+        let spent = self.total_spent_minutes();
+        let running = if self.timer_started_at.is_some() {
+            " *"
+        } else {
+            ""
+        };
+
+        match self.estimate_minutes {
+            Some(estimate) => Some(format!("({}/{}m{})", spent, estimate, running)),
+            None if spent > 0 || self.timer_started_at.is_some() => {
+                Some(format!("({}m{})", spent, running))
+            }
+            None => None,
         }
     }
 }
+
+#[derive(Deserialize)]
+struct StoredTask {
+    text: String,
+    #[serde(default)]
+    kind: TaskKind,
+    #[serde(default)]
+    status: Option<TaskStatus>,
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    completed_at: Option<u64>,
+    #[serde(default)]
+    estimate_minutes: Option<u32>,
+    #[serde(default)]
+    spent_seconds: Option<u64>,
+    #[serde(default)]
+    spent_minutes: u32,
+    #[serde(default)]
+    timer_started_at: Option<u64>,
+    #[serde(default)]
+    indent_level: u8,
+    #[serde(default)]
+    collapsed: bool,
+}
+impl From<StoredTask> for Task {
+    fn from(stored: StoredTask) -> Self {
+        let status = match (stored.status, stored.completed) {
+            (Some(status), _) => status,
+            (None, true) => TaskStatus::Done,
+            (None, false) => TaskStatus::Todo,
+        };
+
+        Self {
+            text: stored.text,
+            kind: stored.kind,
+            status,
+            completed_at: stored.completed_at,
+            estimate_minutes: stored.estimate_minutes,
+            spent_seconds: stored
+                .spent_seconds
+                .unwrap_or(stored.spent_minutes as u64 * 60),
+            timer_started_at: stored.timer_started_at,
+            indent_level: stored.indent_level,
+            collapsed: stored.collapsed,
+        }
+    }
+}
+
+pub fn format_age(timestamp: u64) -> String {
+    let seconds = now().saturating_sub(timestamp);
+
+    match seconds {
+        0..=59 => String::from("just now"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Task {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn migrates_completed_flag_to_status() {
+        assert!(parse(r#"{"text":"a","completed":true}"#).status == TaskStatus::Done);
+        assert!(parse(r#"{"text":"a","completed":false}"#).status == TaskStatus::Todo);
+        assert!(parse(r#"{"text":"a"}"#).status == TaskStatus::Todo);
+    }
+
+    #[test]
+    fn status_takes_precedence_over_completed_flag() {
+        let task = parse(r#"{"text":"a","status":"waiting","completed":true}"#);
+
+        assert!(task.status == TaskStatus::Waiting);
+    }
+
+    #[test]
+    fn fills_missing_fields_with_defaults() {
+        let task = parse(r#"{"text":"a","completed":true}"#);
+
+        assert!(task.kind == TaskKind::Task);
+        assert_eq!(task.completed_at, None);
+        assert_eq!(task.spent_seconds, 0);
+        assert_eq!(task.indent_level, 0);
+        assert!(!task.collapsed);
+    }
+
+    #[test]
+    fn migrates_spent_minutes_to_seconds() {
+        assert_eq!(
+            parse(r#"{"text":"a","spent_minutes":3}"#).spent_seconds,
+            180
+        );
+        assert_eq!(
+            parse(r#"{"text":"a","spent_minutes":3,"spent_seconds":200}"#).spent_seconds,
+            200
+        );
+    }
+
+    #[test]
+    fn accumulates_seconds_across_timer_sessions() {
+        let mut task = Task::new(String::from("a"));
+
+        for session in 0..10 {
+            task.timer_started_at = Some(session * 100);
+            task.stop_timer_at(session * 100 + 25);
+        }
+
+        assert_eq!(task.spent_seconds, 250);
+        assert_eq!(task.timer_started_at, None);
+        assert_eq!(task.spent_minutes_at(0), 4);
+    }
+
+    #[test]
+    fn includes_running_session_in_spent_minutes() {
+        let mut task = Task::new(String::from("a"));
+        task.spent_seconds = 50;
+        task.timer_started_at = Some(1000);
+
+        assert_eq!(task.spent_minutes_at(1000), 1);
+        assert_eq!(task.spent_minutes_at(1100), 3);
+
+        task.stop_timer_at(1100);
+        task.stop_timer_at(2000);
+        assert_eq!(task.spent_seconds, 150);
+    }
+
+    #[test]
+    fn normalizes_whitespace() {
+        let mut task = Task::new(String::from("\t buy  milk \n"));
+        task.normalize_whitespace();
+        assert_eq!(task.text, "buy milk");
+
+        let mut task = Task::new(String::from("a    b  c"));
+        task.normalize_whitespace();
+        assert_eq!(task.text, "a b c");
+
+        let mut task = Task::new(String::from("already tidy"));
+        task.normalize_whitespace();
+        assert_eq!(task.text, "already tidy");
+    }
+
+    #[test]
+    fn clamps_indent_level() {
+        let mut task = Task::new(String::from("a"));
+
+        task.outdent();
+        assert_eq!(task.indent_level, 0);
+
+        for _ in 0..MAX_INDENT_LEVEL + 2 {
+            task.indent();
+        }
+        assert_eq!(task.indent_level, MAX_INDENT_LEVEL);
+        assert_eq!(
+            task.indentation(),
+            " ".repeat(2 * MAX_INDENT_LEVEL as usize)
+        );
+
+        task.outdent();
+        assert_eq!(task.indent_level, MAX_INDENT_LEVEL - 1);
+    }
+
+    #[test]
+    fn indents_by_two_spaces_per_level() {
+        let mut task = Task::new(String::from("a"));
+        assert_eq!(task.indentation(), "");
+
+        task.indent();
+        task.indent();
+        assert_eq!(task.indentation(), "    ");
+    }
+}
@@ -1,32 +1,211 @@
 use std::{
-    fs::File,
-    io::{prelude::*, BufReader, Error, Write},
-    net::TcpStream,
-    path::PathBuf,
+    collections::BTreeMap,
+    fs,
+    io::{prelude::*, BufReader, Error, ErrorKind, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     str,
+    time::{Duration, Instant, SystemTime},
 };
 
 use directories::ProjectDirs;
 use ratatui::{
     style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
     widgets::ListItem,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{ensure_dir_exists, task::Task, Mode};
+use crate::{
+    storage::StorageFormat,
+    task::{self, Task, TaskKind, TaskStatus},
+    Mode,
+};
+
+const TEMPLATES_DIR: &str = "templates";
+const SERVER_ENV_VAR: &str = "TODOT_SERVER";
+const RECENTLY_DONE_LIMIT: usize = 10;
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+const PENDING_SYNC_FILE: &str = "pending_sync";
+const LEGEND: [(TaskStatus, &str); 3] = [
+    (TaskStatus::Todo, "To do"),
+    (TaskStatus::Waiting, "Waiting"),
+    (TaskStatus::Done, "Done"),
+];
+const DEFAULT_POLL_INTERVAL_MS: u64 = 250;
+const MIN_POLL_INTERVAL_MS: u64 = 10;
+const MAX_POLL_INTERVAL_MS: u64 = 5000;
+const HISTOGRAM_DAYS: u64 = 7;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const ARCHIVE_FILE: &str = "archive.jsonl";
+const UI_STATE_FILE: &str = "ui_state.json";
+const LOCK_FILE: &str = "todot.lock";
+const LOCK_REFRESH: Duration = Duration::from_secs(30);
+const STALE_LOCK_AGE: u64 = 120;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+const PENDING_SYNC_RETRY: Duration = Duration::from_secs(30);
+
+pub type TaskFilter = Box<dyn Fn(&Task) -> bool>;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsertPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BorderStyle {
+    None,
+    #[default]
+    Plain,
+    Rounded,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewDensity {
+    #[default]
+    Compact,
+    Expanded,
+}
 
-const DB_FILE: &str = "db.json";
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct UiState {
+    completed_collapsed: bool,
+    display_reversed: bool,
+    view_density: ViewDensity,
+    show_detail_pane: bool,
+    selected_task: Option<String>,
+}
 
 #[derive(Clone, Serialize, Deserialize)]
-struct Options {
-    server_address: Option<String>,
+pub struct Options {
+    pub server_address: Option<String>,
+    pub max_task_length: Option<usize>,
+    #[serde(default)]
+    pub truncate_long_tasks: bool,
+    #[serde(default)]
+    pub insert_position: InsertPosition,
+    pub backup_server_address: Option<String>,
+    #[serde(default)]
+    pub show_indices: bool,
+    #[serde(default)]
+    pub autocapitalize: bool,
+    pub empty_message: Option<String>,
+    pub no_matches_message: Option<String>,
+    #[serde(default)]
+    pub border_style: BorderStyle,
+    pub tasks_title: Option<String>,
+    pub input_title: Option<String>,
+    pub sync_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub bell_on_complete: bool,
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub normalize_whitespace: bool,
+    #[serde(default)]
+    pub rapid_insert: bool,
+    #[serde(default)]
+    pub skip_dividers: bool,
+    #[serde(default)]
+    pub markdown_emphasis: bool,
+    #[serde(default)]
+    pub format: StorageFormat,
+    #[serde(default)]
+    pub quick_delete: bool,
+    pub autosave_idle_secs: Option<u64>,
+    #[serde(default)]
+    pub show_hints: bool,
+    pub poll_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub remember_discarded_input: bool,
+    #[serde(default)]
+    pub show_last_sync: bool,
+    pub auto_archive_after_days: Option<u64>,
+    pub auto_archive_completed_after_secs: Option<u64>,
+    #[serde(default)]
+    pub group_by_tag: bool,
+    #[serde(default)]
+    pub group_by_first_tag: bool,
+    #[serde(skip)]
+    config_warning: Option<String>,
 }
 impl Options {
     fn default() -> Self {
         Self {
             server_address: None,
+            max_task_length: None,
+            truncate_long_tasks: false,
+            insert_position: InsertPosition::Bottom,
+            backup_server_address: None,
+            show_indices: false,
+            autocapitalize: false,
+            empty_message: None,
+            no_matches_message: None,
+            border_style: BorderStyle::Plain,
+            tasks_title: None,
+            input_title: None,
+            sync_interval_secs: None,
+            bell_on_complete: false,
+            log_level: None,
+            normalize_whitespace: false,
+            rapid_insert: false,
+            skip_dividers: false,
+            markdown_emphasis: false,
+            format: StorageFormat::Json,
+            quick_delete: false,
+            autosave_idle_secs: None,
+            show_hints: false,
+            poll_interval_ms: None,
+            remember_discarded_input: false,
+            show_last_sync: false,
+            auto_archive_after_days: None,
+            auto_archive_completed_after_secs: None,
+            group_by_tag: false,
+            group_by_first_tag: false,
+            config_warning: None,
+        }
+    }
+
+    fn with_warning(warning: String) -> Self {
+        Self {
+            config_warning: Some(warning),
+            ..Self::default()
+        }
+    }
+
+    fn disable_invalid_server_addresses(&mut self) -> Option<String> {
+        let mut warnings = Vec::new();
+
+        for (name, address) in [
+            ("server_address", &mut self.server_address),
+            ("backup_server_address", &mut self.backup_server_address),
+        ] {
+            if let Some(Err(e)) = address.as_deref().map(validate_server_address) {
+                warnings.push(format!("Invalid {} ({}), sync disabled", name, e));
+                *address = None;
+            }
+        }
+
+        match warnings.is_empty() {
+            true => None,
+            false => Some(warnings.join("; ")),
         }
     }
+
+    pub fn poll_interval(&self) -> Duration {
+        let millis = self
+            .poll_interval_ms
+            .unwrap_or(DEFAULT_POLL_INTERVAL_MS)
+            .clamp(MIN_POLL_INTERVAL_MS, MAX_POLL_INTERVAL_MS);
+
+        Duration::from_millis(millis)
+    }
 }
 
 pub struct Todo {
@@ -34,187 +213,2913 @@ pub struct Todo {
     pub new_task_text: String,
     pub mode: Mode,
     pub current_task: usize,
+    pub completed_collapsed: bool,
+    pub display_reversed: bool,
+    pub view_density: ViewDensity,
+    pub insert_index: Option<usize>,
+    pub insert_kind: TaskKind,
+    pub pending_key: Option<char>,
+    pub recent_task: usize,
+    pub palette_task: usize,
+    pub options: Options,
+    pub colors_enabled: bool,
+    pub ring_bell: bool,
+    pub notice: Option<String>,
+    pub read_only: bool,
+    pub show_legend: bool,
+    pub show_detail_pane: bool,
+    pub show_tag_sidebar: bool,
+    pub tag_filter: Option<String>,
+    pub last_discarded_input: Option<String>,
+    pub last_synced_at: Option<u64>,
+    sync_offline: bool,
+    pub last_input: Instant,
+    dirty: bool,
+    owns_lock: bool,
+    lock_refreshed: Instant,
+    db_modified: Option<SystemTime>,
+    db_changed_at: Option<Instant>,
+    pending_sync: bool,
+    last_sync_attempt: Instant,
 }
 impl Todo {
     pub fn new() -> Self {
+        Self::with_options(get_options())
+    }
+
+    fn with_options(options: Options) -> Self {
         Self {
             tasks: Vec::new(),
             new_task_text: String::new(),
             mode: Mode::Normal,
             current_task: 0,
+            completed_collapsed: false,
+            display_reversed: false,
+            view_density: ViewDensity::Compact,
+            insert_index: None,
+            insert_kind: TaskKind::Task,
+            pending_key: None,
+            recent_task: 0,
+            palette_task: 0,
+            options,
+            colors_enabled: colors_supported(),
+            ring_bell: false,
+            notice: None,
+            read_only: false,
+            show_legend: false,
+            show_detail_pane: false,
+            show_tag_sidebar: false,
+            tag_filter: None,
+            last_discarded_input: None,
+            last_synced_at: None,
+            sync_offline: false,
+            last_input: Instant::now(),
+            dirty: false,
+            owns_lock: false,
+            lock_refreshed: Instant::now(),
+            db_modified: None,
+            db_changed_at: None,
+            pending_sync: false,
+            last_sync_attempt: Instant::now(),
         }
     }
 
     pub fn insert(&mut self, text: String) {
-        self.tasks.insert(self.tasks.len(), Task::new(text));
-        self.current_task = self.tasks.len() - 1;
+        self.insert_at(self.new_task_index(), text);
+    }
+
+    pub fn add(&mut self, text: String) -> Result<(), Error> {
+        self.insert_task(self.new_task_index(), text);
+
+        self.save()
+    }
+
+    pub fn insert_at(&mut self, index: usize, text: String) {
+        self.insert_task(index, text);
+
+        self.save_or_notify();
+    }
+
+    fn new_task_index(&self) -> usize {
+        match self.options.insert_position {
+            InsertPosition::Top => 0,
+            InsertPosition::Bottom => self.tasks.len(),
+        }
+    }
+
+    fn insert_task(&mut self, index: usize, text: String) {
+        let index = index.min(self.tasks.len());
+
+        let mut task = Task::new(text);
+        task.kind = std::mem::take(&mut self.insert_kind);
+
+        self.tasks.insert(index, task);
+        self.current_task = index;
+
+        self.clamp_selection_to_visible();
+    }
+
+    pub fn split(&mut self, index: usize, lines: Vec<String>) {
+        let indent_level = match self.tasks.get(index) {
+            Some(task) if !task.is_divider() => task.indent_level,
+            _ => return,
+        };
+
+        let pieces: Vec<Task> = lines
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .map(|line| Task {
+                indent_level,
+                ..Task::new(line)
+            })
+            .collect();
+
+        if pieces.is_empty() {
+            return;
+        }
+
+        self.tasks.splice(index..=index, pieces);
+        self.current_task = index;
 
-        self.save().unwrap();
+        self.save_or_notify();
     }
 
     pub fn toggle(&mut self) {
         let found_task = self.tasks.get_mut(self.current_task);
 
-        match found_task {
-            Some(task) => {
-                task.completed = !task.completed;
+        if let Some(task) = found_task {
+            task.set_completed(!task.is_done());
+            self.ring_bell = task.is_done() && self.options.bell_on_complete;
+        }
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn complete(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            if !task.is_done() {
+                task.set_completed(true);
+                self.ring_bell = self.options.bell_on_complete;
             }
-            None => {}
         }
 
-        self.save().unwrap();
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn toggle_visible(&mut self) {
+        for index in self.visible_indices() {
+            let task = &mut self.tasks[index];
+            task.set_completed(!task.is_done());
+        }
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn cycle_status(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            task.set_status(task.status.next());
+            self.ring_bell = task.is_done() && self.options.bell_on_complete;
+        }
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
     }
 
-    pub fn list(&self) -> Vec<ListItem> {
+    pub fn list(&self) -> Vec<ListItem<'_>> {
         let mut items = Vec::new();
+        let rows = self.row_prefixes();
 
-        for (_, task) in self.tasks.iter().enumerate() {
-            let formated_status = if task.completed { "[x]" } else { "[ ]" };
+        for (header, indices) in self.list_sections() {
+            if let Some(header) = header {
+                items.push(
+                    ListItem::new(header).style(Style::default().add_modifier(Modifier::BOLD)),
+                );
+            }
 
-            let list_item = ListItem::new(format!("{} {}", formated_status, task.text));
+            for index in indices {
+                let prefix = rows
+                    .iter()
+                    .find(|(row, _)| *row == index)
+                    .map_or("", |(_, prefix)| prefix.as_str());
+                let fold = self
+                    .has_subtasks(index)
+                    .then_some(self.tasks[index].collapsed);
 
-            let style = match self.current_task == items.len() {
-                true => Style::default().add_modifier(Modifier::BOLD),
-                false => Style::default(),
-            };
+                items.push(self.list_item(
+                    &self.tasks[index],
+                    self.current_task == index,
+                    prefix,
+                    fold,
+                ));
+            }
+        }
 
-            let style = match task.completed {
-                true => style.fg(Color::Green),
-                false => style.fg(Color::Yellow),
-            };
+        let collapsed = match self.completed_collapsed {
+            true => self.tasks.iter().filter(|task| task.is_done()).count(),
+            false => 0,
+        };
 
-            items.push(list_item.style(style));
+        if collapsed > 0 {
+            items.push(
+                ListItem::new(format!("▸ {} completed", collapsed))
+                    .style(self.fg(Style::default(), Color::Green)),
+            );
         }
 
         items
     }
 
-    pub fn delete(&mut self) {
+    fn row_prefixes(&self) -> Vec<(usize, String)> {
+        let visible = self.visible_indices();
+        let width = visible.len().to_string().len();
+
+        visible
+            .into_iter()
+            .enumerate()
+            .map(|(position, index)| match self.options.show_indices {
+                true => (index, format!("{:>width$}. ", position + 1, width = width)),
+                false => (index, String::new()),
+            })
+            .collect()
+    }
+
+    pub fn toggle_view_density(&mut self) {
+        self.view_density = match self.view_density {
+            ViewDensity::Compact => ViewDensity::Expanded,
+            ViewDensity::Expanded => ViewDensity::Compact,
+        };
+    }
+
+    pub fn selected_position(&self) -> Option<usize> {
+        let mut position = 0;
+
+        for (header, indices) in self.list_sections() {
+            position += usize::from(header.is_some());
+
+            match indices.iter().position(|&index| index == self.current_task) {
+                Some(offset) => return Some(position + offset),
+                None => position += indices.len(),
+            }
+        }
+
+        None
+    }
+
+    fn list_sections(&self) -> Vec<(Option<String>, Vec<usize>)> {
+        match self.options.group_by_tag {
+            true => self
+                .tag_groups()
+                .into_iter()
+                .map(|(header, indices)| (Some(header), indices))
+                .collect(),
+            false => vec![(None, self.visible_indices())],
+        }
+    }
+
+    pub fn tag_groups(&self) -> Vec<(String, Vec<usize>)> {
+        let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        let mut untagged = Vec::new();
+
+        for index in self.visible_in_order() {
+            let mut tags = self.tasks[index].tags();
+
+            if self.options.group_by_first_tag {
+                tags.truncate(1);
+            }
+
+            if tags.is_empty() {
+                untagged.push(index);
+            }
+
+            for tag in tags {
+                groups.entry(tag).or_default().push(index);
+            }
+        }
+
+        let mut groups: Vec<(String, Vec<usize>)> = groups
+            .into_iter()
+            .map(|(tag, indices)| (format!("#{}", tag), indices))
+            .collect();
+
+        if !untagged.is_empty() {
+            groups.push((String::from("(untagged)"), untagged));
+        }
+
+        groups
+    }
+
+    pub fn placeholder(&self) -> Option<String> {
         if self.tasks.is_empty() {
+            return Some(
+                self.options
+                    .empty_message
+                    .clone()
+                    .unwrap_or_else(|| String::from("No tasks — press i to add one")),
+            );
+        }
+
+        if self.visible_indices().is_empty() {
+            return Some(
+                self.options
+                    .no_matches_message
+                    .clone()
+                    .unwrap_or_else(|| String::from("No matches")),
+            );
+        }
+
+        None
+    }
+
+    pub fn is_visible(&self, index: usize) -> bool {
+        match self.tasks.get(index) {
+            Some(task) if self.completed_collapsed && task.is_done() => false,
+            Some(task)
+                if self
+                    .tag_filter
+                    .as_ref()
+                    .is_some_and(|tag| !task.has_tag(tag)) =>
+            {
+                false
+            }
+            Some(_) => !self.is_folded_away(index),
+            None => false,
+        }
+    }
+
+    fn is_folded_away(&self, index: usize) -> bool {
+        self.ancestors(index)
+            .into_iter()
+            .any(|ancestor| self.tasks[ancestor].collapsed)
+    }
+
+    fn ancestors(&self, index: usize) -> Vec<usize> {
+        let mut ancestors = Vec::new();
+        let mut indent_level = self.tasks[index].indent_level;
+
+        for ancestor in (0..index).rev() {
+            if indent_level == 0 {
+                break;
+            }
+
+            if self.tasks[ancestor].indent_level < indent_level {
+                ancestors.push(ancestor);
+                indent_level = self.tasks[ancestor].indent_level;
+            }
+        }
+
+        ancestors
+    }
+
+    fn unfold_ancestors(&mut self, index: usize) -> bool {
+        let mut unfolded = false;
+
+        for ancestor in self.ancestors(index) {
+            if self.tasks[ancestor].collapsed {
+                self.tasks[ancestor].collapsed = false;
+                unfolded = true;
+            }
+        }
+
+        unfolded
+    }
+
+    fn has_subtasks(&self, index: usize) -> bool {
+        match (self.tasks.get(index), self.tasks.get(index + 1)) {
+            (Some(task), Some(next)) => next.indent_level > task.indent_level,
+            _ => false,
+        }
+    }
+
+    fn unfold_childless_tasks(&mut self) {
+        for index in 0..self.tasks.len() {
+            if self.tasks[index].collapsed && !self.has_subtasks(index) {
+                self.tasks[index].collapsed = false;
+            }
+        }
+    }
+
+    pub fn toggle_subtasks(&mut self) {
+        if !self.has_subtasks(self.current_task) {
             return;
         }
 
-        self.tasks.remove(self.current_task);
+        let task = &mut self.tasks[self.current_task];
+        task.collapsed = !task.collapsed;
 
-        self.current_task = if self.current_task > 0 {
-            self.current_task - 1
-        } else {
-            0
-        };
+        self.save_or_notify();
+    }
 
-        self.save().unwrap();
+    fn is_selectable(&self, index: usize) -> bool {
+        !(self.options.skip_dividers && self.tasks[index].is_divider())
     }
 
-    pub fn save(&self) -> Result<(), std::io::Error> {
-        let data = serde_json::to_string(&self.tasks)?;
+    pub fn visible_indices(&self) -> Vec<usize> {
+        if !self.options.group_by_tag {
+            return self.visible_in_order();
+        }
 
-        let path = get_database_path();
+        let mut indices: Vec<usize> = Vec::new();
 
-        match get_options().server_address {
-            Some(server_address) => {
-                send_tasks_to_server(&self, server_address).expect("Unable to send tasks to server")
+        for (_, group) in self.tag_groups() {
+            for index in group {
+                if !indices.contains(&index) {
+                    indices.push(index);
+                }
             }
-            None => std::fs::write(path, data).expect("Unable to write file"),
         }
 
-        Ok(())
+        indices
+    }
+
+    fn visible_in_order(&self) -> Vec<usize> {
+        let indices = (0..self.tasks.len()).filter(|&index| self.is_visible(index));
+
+        match self.display_reversed {
+            true => indices.rev().collect(),
+            false => indices.collect(),
+        }
+    }
+
+    pub fn toggle_display_reversed(&mut self) {
+        self.display_reversed = !self.display_reversed;
+    }
+
+    pub fn neighbour_index(&self, below: bool) -> usize {
+        if self.tasks.is_empty() {
+            return 0;
+        }
+
+        match below != self.display_reversed {
+            true => self.current_task + 1,
+            false => self.current_task,
+        }
+    }
+
+    pub fn toggle_completed_collapsed(&mut self) {
+        self.completed_collapsed = !self.completed_collapsed;
+
+        self.clamp_selection_to_visible();
     }
 
-    pub fn load() -> Result<Todo, std::io::Error> {
-        let path = get_database_path();
+    pub fn clamp_selection_to_visible(&mut self) {
+        let visible = self.visible_indices();
 
-        let data = std::fs::read_to_string(path)?;
+        if visible.contains(&self.current_task) {
+            return;
+        }
+
+        self.current_task = visible
+            .into_iter()
+            .min_by_key(|index| index.abs_diff(self.current_task))
+            .unwrap_or(0);
+    }
+
+    pub fn fg(&self, style: Style, color: Color) -> Style {
+        match self.colors_enabled {
+            true => style.fg(color),
+            false => style,
+        }
+    }
+
+    fn list_item<'a>(
+        &self,
+        task: &'a Task,
+        selected: bool,
+        prefix: &str,
+        fold: Option<bool>,
+    ) -> ListItem<'a> {
+        let fold_marker = match fold {
+            Some(true) => "▸ ",
+            Some(false) => "▾ ",
+            None => "",
+        };
+
+        let formated_status = status_marker(task.status);
+
+        if task.is_divider() {
+            let style = match selected {
+                true => Style::default().add_modifier(Modifier::BOLD),
+                false => Style::default().add_modifier(Modifier::DIM),
+            };
+
+            return ListItem::new(format!(
+                "{}{}{}── {} ──",
+                prefix,
+                task.indentation(),
+                fold_marker,
+                task.text
+            ))
+            .style(style);
+        }
+
+        let mut first_line = vec![Span::raw(format!(
+            "{}{}{}{} ",
+            prefix,
+            task.indentation(),
+            fold_marker,
+            formated_status
+        ))];
 
-        let mut todo = Todo::new();
-        todo.tasks = match get_options().server_address {
-            Some(server_address) => {
-                read_tasks_from_server(server_address).expect("Failed to read tasks from server")
+        match self.options.markdown_emphasis {
+            true => first_line.extend(emphasis_spans(&task.text)),
+            false => first_line.push(Span::raw(task.text.as_str())),
+        }
+
+        let mut lines = Vec::new();
+
+        match self.view_density {
+            ViewDensity::Compact => {
+                if let Some(effort) = task.effort() {
+                    first_line.push(Span::raw(format!(" {}", effort)));
+                }
+            }
+            ViewDensity::Expanded => {
+                let details = task.details();
+
+                if !details.is_empty() {
+                    lines.push(Line::from(format!(
+                        "{}{}    {}",
+                        " ".repeat(prefix.chars().count()),
+                        task.indentation(),
+                        details.join(" · ")
+                    )));
+                }
             }
-            None => serde_json::from_str(&data)?,
+        }
+
+        lines.insert(0, Line::from(first_line));
+
+        let list_item = ListItem::new(Text::from(lines));
+
+        let style = match selected {
+            true => Style::default().add_modifier(Modifier::BOLD),
+            false => Style::default(),
         };
 
-        Ok(todo)
+        list_item.style(self.fg(style, status_color(task.status)))
     }
 
-    pub fn prev(&mut self) {
-        if self.current_task > 0 {
-            self.current_task -= 1;
+    pub fn legend(&self) -> Vec<ListItem<'_>> {
+        let mut items: Vec<ListItem> = LEGEND
+            .iter()
+            .map(|(status, meaning)| {
+                ListItem::new(format!("{} {}", status_marker(*status), meaning))
+                    .style(self.fg(Style::default(), status_color(*status)))
+            })
+            .collect();
+
+        items.push(
+            ListItem::new("── Divider ──").style(Style::default().add_modifier(Modifier::DIM)),
+        );
+
+        items
+    }
+
+    pub fn detail_lines(&self) -> Vec<String> {
+        let task = match self.tasks.get(self.current_task) {
+            Some(task) => task,
+            None => return Vec::new(),
+        };
+
+        let mut lines = vec![task.text.clone()];
+
+        if task.is_divider() {
+            return lines;
+        }
+
+        lines.push(String::new());
+
+        if let Some((_, meaning)) = LEGEND.iter().find(|(status, _)| *status == task.status) {
+            lines.push(format!("Status: {}", meaning));
+        }
+
+        lines.extend(task.details());
+
+        let subtasks: Vec<String> = self.tasks[self.current_task + 1..]
+            .iter()
+            .take_while(|subtask| subtask.indent_level > task.indent_level)
+            .map(|subtask| {
+                format!(
+                    "{}{} {}",
+                    "  ".repeat((subtask.indent_level - task.indent_level - 1) as usize),
+                    status_marker(subtask.status),
+                    subtask.text
+                )
+            })
+            .collect();
+
+        if !subtasks.is_empty() {
+            lines.push(String::new());
+            lines.push(String::from("Subtasks:"));
+            lines.extend(subtasks);
         }
+
+        lines
     }
 
-    pub fn next(&mut self) {
-        if self.current_task < self.tasks.len() - 1 {
-            self.current_task += 1;
+    pub fn tag_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+
+        for task in self.tasks.iter().filter(|task| !task.is_divider()) {
+            for tag in task.tags() {
+                *counts.entry(tag).or_default() += usize::from(!task.is_done());
+            }
         }
+
+        counts.into_iter().collect()
     }
-}
 
-fn get_database_path() -> PathBuf {
-    let path = ProjectDirs::from("eu", "tortitas", "todot")
-        .unwrap()
-        .data_dir()
-        .to_path_buf();
+    pub fn list_tag_counts(&self) -> Vec<ListItem<'_>> {
+        self.tag_counts()
+            .into_iter()
+            .map(|(tag, count)| {
+                let style = match self.tag_filter.as_ref() == Some(&tag) {
+                    true => Style::default().add_modifier(Modifier::REVERSED),
+                    false => Style::default(),
+                };
 
-    ensure_dir_exists(&path).unwrap();
+                ListItem::new(format!("#{} ({})", tag, count)).style(style)
+            })
+            .collect()
+    }
 
-    path.join(DB_FILE)
-}
+    pub fn toggle_tag_sidebar(&mut self) {
+        self.show_tag_sidebar = !self.show_tag_sidebar;
 
-fn send_tasks_to_server(todo: &Todo, server_address: String) -> Result<(), Error> {
-    let mut input = String::from("write\n");
+        if !self.show_tag_sidebar {
+            self.tag_filter = None;
+        }
+    }
 
-    input.push_str(
-        serde_json::to_string(&todo.tasks)
-            .expect("Failed to serialize tasks")
-            .as_str(),
-    );
+    pub fn cycle_tag_filter(&mut self) {
+        if !self.show_tag_sidebar {
+            return;
+        }
 
-    let mut stream = TcpStream::connect(server_address)?;
+        let tags: Vec<String> = self.tag_counts().into_iter().map(|(tag, _)| tag).collect();
 
-    stream.write(input.as_bytes()).expect("Failed to write");
+        self.tag_filter = match &self.tag_filter {
+            Some(current) => tags
+                .iter()
+                .skip_while(|tag| *tag != current)
+                .nth(1)
+                .cloned(),
+            None => tags.first().cloned(),
+        };
 
-    let mut reader = BufReader::new(&stream);
-    let mut buffer: Vec<u8> = Vec::new();
-    reader.read_until(b'\n', &mut buffer)?;
+        self.clamp_selection_to_visible();
+    }
 
-    Ok(())
-}
+    pub fn toggle_detail_pane(&mut self) {
+        self.show_detail_pane = !self.show_detail_pane;
+    }
 
-fn read_tasks_from_server(server_address: String) -> Result<Vec<Task>, Error> {
-    let input = String::from("read\n");
+    pub fn toggle_legend(&mut self) {
+        self.show_legend = !self.show_legend;
+    }
 
-    let mut stream = TcpStream::connect(server_address)?;
+    pub fn recently_completed(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.tasks.len())
+            .filter(|&index| self.tasks[index].is_done())
+            .collect();
 
-    stream.write(input.as_bytes()).expect("Failed to write");
+        indices.sort_by(|a, b| {
+            self.tasks[*b]
+                .completed_at
+                .cmp(&self.tasks[*a].completed_at)
+        });
+        indices.truncate(RECENTLY_DONE_LIMIT);
 
-    let mut reader = BufReader::new(&stream);
-    let mut buffer: Vec<u8> = Vec::new();
-    reader.read_until(b'\n', &mut buffer)?;
+        indices
+    }
 
-    let response = str::from_utf8(&buffer).unwrap();
+    pub fn list_recently_completed(&self) -> Vec<ListItem<'_>> {
+        let mut items = Vec::new();
 
-    serde_json::from_str(&response).map_err(|e| e.into())
-}
+        for index in self.recently_completed() {
+            items.push(self.list_item(
+                &self.tasks[index],
+                self.recent_task == items.len(),
+                "",
+                None,
+            ));
+        }
+
+        items
+    }
 
-fn get_options() -> Options {
-    let path = ProjectDirs::from("eu", "tortitas", "todot")
-        .unwrap()
-        .config_dir()
-        .to_path_buf();
+    pub fn completions_per_day(&self) -> Vec<(String, u64)> {
+        let today = task::now() / SECONDS_PER_DAY;
 
-    ensure_dir_exists(&path).unwrap();
+        (0..HISTOGRAM_DAYS)
+            .rev()
+            .map(|days_ago| {
+                let count = self
+                    .tasks
+                    .iter()
+                    .filter(|task| task.is_done())
+                    .filter_map(|task| task.completed_at)
+                    .filter(|completed_at| completed_at / SECONDS_PER_DAY + days_ago == today)
+                    .count() as u64;
 
-    let path = path.join("config.toml");
+                let label = match days_ago {
+                    0 => String::from("today"),
+                    _ => format!("-{}d", days_ago),
+                };
 
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => return Options::default(),
-    };
+                (label, count)
+            })
+            .collect()
+    }
+
+    pub fn prev_recent(&mut self) {
+        if self.recent_task > 0 {
+            self.recent_task -= 1;
+        }
+    }
 
-    let mut contents = String::new();
+    pub fn next_recent(&mut self) {
+        if self.recent_task + 1 < self.recently_completed().len() {
+            self.recent_task += 1;
+        }
+    }
+
+    pub fn toggle_recent(&mut self) {
+        if let Some(&index) = self.recently_completed().get(self.recent_task) {
+            self.current_task = index;
+            self.toggle();
+        }
+
+        self.recent_task = self
+            .recent_task
+            .min(self.recently_completed().len().saturating_sub(1));
+    }
+
+    pub fn palette_matches(&self) -> Vec<usize> {
+        (0..self.tasks.len())
+            .filter(|&index| fuzzy_match(&self.new_task_text, &self.tasks[index].text))
+            .collect()
+    }
 
-    file.read_to_string(&mut contents)
-        .expect("Failed to read config file");
+    pub fn list_palette_matches(&self) -> Vec<ListItem<'_>> {
+        let mut items = Vec::new();
+
+        for index in self.palette_matches() {
+            items.push(self.list_item(
+                &self.tasks[index],
+                self.palette_task == items.len(),
+                "",
+                None,
+            ));
+        }
+
+        items
+    }
 
-    toml::from_str(&contents).expect("Failed to parse config file")
+    pub fn prev_palette(&mut self) {
+        if self.palette_task > 0 {
+            self.palette_task -= 1;
+        }
+    }
+
+    pub fn next_palette(&mut self) {
+        if self.palette_task + 1 < self.palette_matches().len() {
+            self.palette_task += 1;
+        }
+    }
+
+    pub fn clamp_palette_selection(&mut self) {
+        self.palette_task = self
+            .palette_task
+            .min(self.palette_matches().len().saturating_sub(1));
+    }
+
+    pub fn jump_to_palette_selection(&mut self) {
+        if let Some(&index) = self.palette_matches().get(self.palette_task) {
+            if self.completed_collapsed && self.tasks[index].is_done() {
+                self.completed_collapsed = false;
+            }
+
+            self.current_task = index;
+
+            if self.unfold_ancestors(index) {
+                self.save_or_notify();
+            }
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+
+        self.tasks.remove(self.current_task);
+
+        self.current_task = if self.current_task > 0 {
+            self.current_task - 1
+        } else {
+            0
+        };
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn dedup(&mut self) {
+        let selected_text = self
+            .tasks
+            .get(self.current_task)
+            .map(|task| task.text.clone());
+
+        let mut unique: Vec<Task> = Vec::new();
+
+        for task in self.tasks.drain(..) {
+            let duplicate = unique.iter_mut().find(|kept| {
+                kept.text == task.text
+                    && kept.kind == task.kind
+                    && kept.indent_level == task.indent_level
+            });
+
+            match duplicate {
+                Some(kept) => {
+                    if task.is_done() && !kept.is_done() {
+                        kept.set_status(TaskStatus::Done);
+                        kept.completed_at = task.completed_at.or(kept.completed_at);
+                    }
+                }
+                None => unique.push(task),
+            }
+        }
+
+        self.tasks = unique;
+        self.current_task = selected_text
+            .and_then(|text| self.tasks.iter().position(|task| task.text == text))
+            .unwrap_or(0);
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn save(&mut self) -> Result<(), std::io::Error> {
+        self.unfold_childless_tasks();
+
+        if self.options.normalize_whitespace {
+            self.tasks.iter_mut().for_each(Task::normalize_whitespace);
+        }
+
+        if self.read_only {
+            self.notice = Some(String::from("Read-only: changes are not saved"));
+            return Ok(());
+        }
+
+        self.write_local()?;
+
+        if let Some(server_address) = self.options.server_address.clone() {
+            self.push_to_server(server_address);
+        }
+
+        Ok(())
+    }
+
+    pub fn load_tasks(&mut self) -> Result<(), std::io::Error> {
+        let warnings: Vec<String> = [
+            self.options.config_warning.take(),
+            self.options.disable_invalid_server_addresses(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if !warnings.is_empty() {
+            let warning = warnings.join("; ");
+            log::warn!("{}", warning);
+            self.notice = Some(warning);
+        }
+
+        self.pending_sync = get_pending_sync_path().is_ok_and(|path| path.exists());
+
+        match self.options.server_address.clone() {
+            Some(server_address) if self.pending_sync => {
+                log::info!("Loading local tasks with unsynced changes");
+                self.tasks = read_local_tasks(self.options.format)?;
+                self.push_to_server(server_address);
+            }
+            Some(server_address) => match read_tasks_from_server(server_address, SYNC_TIMEOUT) {
+                Ok(tasks) => {
+                    log::info!("Loaded {} tasks from server", tasks.len());
+                    self.mark_synced(true);
+                    self.tasks = tasks;
+                    self.write_local()?;
+                }
+                Err(e) => {
+                    log::warn!("Unable to read tasks from server, using local copy: {}", e);
+                    self.mark_synced(false);
+                    self.tasks = read_local_tasks(self.options.format)?;
+                }
+            },
+            None => self.tasks = read_local_tasks(self.options.format)?,
+        }
+
+        log::info!("Loaded {} tasks", self.tasks.len());
+
+        self.db_modified = get_database_modified(self.options.format);
+
+        if let Some(days) = self.options.auto_archive_after_days {
+            let notice = match self
+                .archive_completed_before(task::now().saturating_sub(days * SECONDS_PER_DAY))
+            {
+                Ok(0) => None,
+                Ok(archived) => Some(format!(
+                    "Archived {} tasks completed over {} days ago",
+                    archived, days
+                )),
+                Err(e) => {
+                    log::error!("Unable to archive tasks: {}", e);
+                    Some(format!("Unable to archive tasks: {}", e))
+                }
+            };
+
+            if let Some(notice) = notice {
+                self.notice = Some(match self.notice.take() {
+                    Some(warning) => format!("{}; {}", warning, notice),
+                    None => notice,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn archive_completed_before(&mut self, cutoff: u64) -> Result<usize, Error> {
+        if self.read_only
+            || !self.owns_lock
+            || !self.tasks.iter().any(|task| completed_before(task, cutoff))
+        {
+            return Ok(0);
+        }
+
+        let (archived, kept) = split_archivable(&self.tasks, cutoff);
+
+        let path = get_data_dir()?.join(ARCHIVE_FILE);
+        let archive_length = append_to_archive(&path, &archived)?;
+
+        let previous = std::mem::take(&mut self.tasks);
+        self.replace_tasks(kept);
+
+        if let Err(e) = self.save() {
+            self.replace_tasks(previous);
+
+            if let Err(e) = truncate_archive(&path, archive_length) {
+                log::error!("Unable to undo appending to {}: {}", ARCHIVE_FILE, e);
+            }
+
+            return Err(e);
+        }
+
+        log::info!("Archived {} completed tasks", archived.len());
+
+        Ok(archived.len())
+    }
+
+    fn save_or_notify(&mut self) {
+        if let Err(e) = self.save() {
+            log::error!("Unable to save tasks: {}", e);
+            self.notice = Some(format!("Unable to save tasks: {}", e));
+            self.dirty = true;
+        }
+    }
+
+    pub fn archive_completed_if_due(&mut self) {
+        let secs = match self.options.auto_archive_completed_after_secs {
+            Some(secs) => secs,
+            None => return,
+        };
+
+        if self.db_changed_at.is_some() {
+            return;
+        }
+
+        if let Err(e) = self.archive_completed_before(task::now().saturating_sub(secs)) {
+            log::error!(
+                "Unable to archive tasks, disabling auto_archive_completed_after_secs: {}",
+                e
+            );
+            self.notice = Some(format!("Unable to archive tasks: {}", e));
+            self.options.auto_archive_completed_after_secs = None;
+        }
+    }
+
+    pub fn autosave_if_idle(&mut self) {
+        let idle = match self.options.autosave_idle_secs {
+            Some(idle) => Duration::from_secs(idle),
+            None => return,
+        };
+
+        if self.dirty && self.last_input.elapsed() >= idle {
+            log::info!("Saving unsaved changes after {}s idle", idle.as_secs());
+            self.save_or_notify();
+            self.last_input = Instant::now();
+        }
+    }
+
+    fn write_local(&mut self) -> Result<(), std::io::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        let changed_on_disk = self.db_changed_at.is_some()
+            || (self.db_modified.is_some()
+                && get_database_modified(self.options.format) != self.db_modified);
+
+        if changed_on_disk {
+            return Err(Error::other(
+                "the database changed on disk, reloading it instead of overwriting",
+            ));
+        }
+
+        let storage = self.options.format.storage();
+        let data = storage.serialize(&self.tasks)?;
+
+        std::fs::write(get_database_path(self.options.format)?, data)?;
+        self.db_modified = get_database_modified(self.options.format);
+        self.db_changed_at = None;
+        self.dirty = false;
+
+        log::debug!(
+            "Saved {} tasks to {}",
+            self.tasks.len(),
+            storage.file_name()
+        );
+
+        Ok(())
+    }
+
+    fn mark_synced(&mut self, succeeded: bool) {
+        if succeeded {
+            self.last_synced_at = Some(task::now());
+        }
+
+        self.sync_offline = !succeeded;
+    }
+
+    pub fn sync_status(&self) -> Option<String> {
+        if !self.options.show_last_sync || self.options.server_address.is_none() {
+            return None;
+        }
+
+        Some(match (self.sync_offline, self.last_synced_at) {
+            (true, Some(synced_at)) => format!("offline, synced {}", task::format_age(synced_at)),
+            (true, None) => String::from("offline"),
+            (false, Some(synced_at)) => format!("synced {}", task::format_age(synced_at)),
+            (false, None) => String::from("never synced"),
+        })
+    }
+
+    fn push_to_server(&mut self, server_address: String) {
+        if self.read_only {
+            return;
+        }
+
+        self.pending_sync = match send_tasks_to_server(self, &server_address) {
+            Ok(()) => {
+                log::info!("Sent {} tasks to {}", self.tasks.len(), server_address);
+                false
+            }
+            Err(e) => {
+                log::error!("Unable to send tasks to {}: {}", server_address, e);
+                true
+            }
+        };
+        self.mark_synced(!self.pending_sync);
+
+        let pending_sync = self.pending_sync;
+
+        let marked = get_pending_sync_path().and_then(|path| match pending_sync {
+            true => std::fs::write(&path, task::now().to_string()),
+            false if path.exists() => std::fs::remove_file(&path),
+            false => Ok(()),
+        });
+
+        if let Err(e) = marked {
+            log::error!("Unable to update {}: {}", PENDING_SYNC_FILE, e);
+            self.notice = Some(format!("Unable to record sync state: {}", e));
+        }
+    }
+
+    pub fn save_ui_state(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        std::fs::write(
+            get_ui_state_path()?,
+            serde_json::to_string(&self.ui_state())?,
+        )
+    }
+
+    fn ui_state(&self) -> UiState {
+        UiState {
+            completed_collapsed: self.completed_collapsed,
+            display_reversed: self.display_reversed,
+            view_density: self.view_density,
+            show_detail_pane: self.show_detail_pane,
+            selected_task: self
+                .tasks
+                .get(self.current_task)
+                .map(|task| task.text.clone()),
+        }
+    }
+
+    pub fn restore_ui_state(&mut self) {
+        let state: UiState = match get_ui_state_path().and_then(std::fs::read_to_string) {
+            Ok(data) => match serde_json::from_str(&data) {
+                Ok(state) => state,
+                Err(e) => {
+                    log::warn!("Ignoring unreadable {}: {}", UI_STATE_FILE, e);
+                    return;
+                }
+            },
+            Err(_) => return,
+        };
+
+        self.apply_ui_state(state);
+    }
+
+    fn apply_ui_state(&mut self, state: UiState) {
+        self.completed_collapsed = state.completed_collapsed;
+        self.display_reversed = state.display_reversed;
+        self.view_density = state.view_density;
+        self.show_detail_pane = state.show_detail_pane;
+
+        if let Some(index) = state
+            .selected_task
+            .and_then(|text| self.tasks.iter().position(|task| task.text == text))
+        {
+            self.current_task = index;
+        }
+
+        self.clamp_selection_to_visible();
+    }
+
+    pub fn acquire_lock(&mut self) {
+        match get_lock_path().and_then(|path| create_lock(&path)) {
+            Ok(()) => {
+                self.owns_lock = true;
+                self.lock_refreshed = Instant::now();
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                log::warn!("Database is locked by another instance, opening read-only");
+                self.read_only = true;
+            }
+            Err(e) => log::warn!("Unable to create {}: {}", LOCK_FILE, e),
+        }
+    }
+
+    pub fn refresh_lock(&mut self) {
+        if !self.owns_lock || self.lock_refreshed.elapsed() < LOCK_REFRESH {
+            return;
+        }
+
+        self.owns_lock = get_lock_path().and_then(|path| write_lock(&path)).is_ok();
+        self.lock_refreshed = Instant::now();
+    }
+
+    pub fn release_lock(&mut self) {
+        if self.owns_lock {
+            let _ = get_lock_path().and_then(std::fs::remove_file);
+            self.owns_lock = false;
+        }
+    }
+
+    pub fn backup_to_server(&self) -> Result<(), Error> {
+        if self.read_only || self.options.server_address.is_some() {
+            return Ok(());
+        }
+
+        match self.options.backup_server_address.clone() {
+            Some(backup_server_address) => send_tasks_to_server(self, &backup_server_address),
+            None => Ok(()),
+        }
+    }
+
+    pub fn save_template(&self, name: &str) -> Result<(), Error> {
+        let data = serde_json::to_string(&self.tasks)?;
+
+        std::fs::write(get_template_path(name)?, data)
+    }
+
+    pub fn apply_template(&mut self, name: &str) -> Result<(), Error> {
+        let data = std::fs::read_to_string(get_template_path(name)?)?;
+        let template: Vec<Task> = serde_json::from_str(&data)?;
+
+        self.tasks
+            .extend(template.iter().map(|task| task.fresh_copy()));
+
+        self.save()
+    }
+
+    pub fn export_jsonl<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        for task in &self.tasks {
+            serde_json::to_writer(&mut writer, task)?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn has_unsynced_changes(&self) -> bool {
+        self.dirty || self.pending_sync
+    }
+
+    pub fn reload(&mut self) {
+        let mut loaded = Todo::with_options(self.options.clone());
+        loaded.read_only = self.read_only;
+
+        match loaded.load_tasks() {
+            Ok(()) => {
+                self.tasks = loaded.tasks;
+                self.db_modified = loaded.db_modified;
+                self.last_synced_at = loaded.last_synced_at.or(self.last_synced_at);
+                self.sync_offline = loaded.sync_offline;
+                self.pending_sync = loaded.pending_sync;
+                self.db_changed_at = None;
+                self.dirty = false;
+
+                self.clamp_selection_to_visible();
+            }
+            Err(e) => {
+                log::error!("Unable to reload tasks: {}", e);
+                self.notice = Some(format!("Unable to reload tasks: {}", e));
+            }
+        }
+    }
+
+    pub fn reload_if_changed(&mut self) {
+        let format = self.options.format;
+
+        self.reload_if_changed_with(get_database_modified(format), Instant::now(), || {
+            read_local_tasks(format)
+        });
+    }
+
+    fn reload_if_changed_with<F>(&mut self, modified: Option<SystemTime>, now: Instant, read: F)
+    where
+        F: FnOnce() -> Result<Vec<Task>, Error>,
+    {
+        if modified != self.db_modified {
+            self.db_modified = modified;
+            self.db_changed_at = Some(now);
+            return;
+        }
+
+        match self.db_changed_at {
+            Some(changed_at) if now.duration_since(changed_at) >= RELOAD_DEBOUNCE => {
+                self.db_changed_at = None;
+            }
+            _ => return,
+        }
+
+        if let Ok(tasks) = read() {
+            self.replace_tasks(tasks);
+            self.dirty = false;
+
+            if self.options.server_address.is_some() {
+                self.pending_sync = get_pending_sync_path().is_ok_and(|path| path.exists());
+            }
+        }
+    }
+
+    pub fn sync_with_server_if_due(&mut self) {
+        let server_address = match self.options.server_address.clone() {
+            Some(server_address) => server_address,
+            None => return,
+        };
+
+        let interval = match (self.pending_sync, self.options.sync_interval_secs) {
+            (true, _) => PENDING_SYNC_RETRY,
+            (false, Some(interval)) => Duration::from_secs(interval),
+            (false, None) => return,
+        };
+
+        if self.last_sync_attempt.elapsed() < interval {
+            return;
+        }
+
+        self.last_sync_attempt = Instant::now();
+
+        if self.pending_sync {
+            self.push_to_server(server_address);
+            return;
+        }
+
+        match read_tasks_from_server(server_address, SYNC_TIMEOUT) {
+            Ok(tasks) => {
+                log::debug!("Refreshed {} tasks from server", tasks.len());
+                self.mark_synced(true);
+                self.replace_tasks(tasks);
+
+                if let Err(e) = self.write_local() {
+                    log::error!("Unable to write refreshed tasks: {}", e);
+                }
+            }
+            Err(e) => {
+                log::warn!("Unable to refresh tasks from server: {}", e);
+                self.mark_synced(false);
+            }
+        }
+    }
+
+    fn replace_tasks(&mut self, tasks: Vec<Task>) {
+        let selected_text = self
+            .tasks
+            .get(self.current_task)
+            .map(|task| task.text.clone());
+
+        self.tasks = tasks;
+        self.current_task = selected_text
+            .and_then(|text| self.tasks.iter().position(|task| task.text == text))
+            .unwrap_or(self.current_task);
+
+        self.clamp_selection_to_visible();
+    }
+
+    pub fn set_estimate(&mut self, estimate_minutes: Option<u32>) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            task.estimate_minutes = estimate_minutes;
+        }
+
+        self.save_or_notify();
+    }
+
+    pub fn indent(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            task.indent();
+        }
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn outdent(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            task.outdent();
+        }
+
+        self.clamp_selection_to_visible();
+
+        self.save_or_notify();
+    }
+
+    pub fn toggle_timer(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.current_task) {
+            task.toggle_timer();
+        }
+
+        self.save_or_notify();
+    }
+
+    pub fn open_url(&mut self) {
+        let url = match self
+            .tasks
+            .get(self.current_task)
+            .and_then(|task| extract_url(&task.text))
+        {
+            Some(url) => url.to_string(),
+            None => {
+                self.notice = Some(String::from("No URL in the selected task"));
+                return;
+            }
+        };
+
+        if cfg!(target_os = "linux")
+            && std::env::var_os("DISPLAY").is_none()
+            && std::env::var_os("WAYLAND_DISPLAY").is_none()
+        {
+            self.notice = Some(format!("No display available to open {}", url));
+            return;
+        }
+
+        let mut command = if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else if cfg!(target_os = "windows") {
+            let mut command = Command::new("rundll32");
+            command.arg("url.dll,FileProtocolHandler");
+            command
+        } else {
+            Command::new("xdg-open")
+        };
+
+        let opened = command
+            .arg(&url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        match opened {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(_) => self.notice = Some(format!("Unable to open {}", url)),
+        }
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.tasks
+            .iter()
+            .filter(|task| !task.is_divider() && !task.is_done())
+            .count()
+    }
+
+    pub fn count_matching(&self, filters: &[TaskFilter]) -> usize {
+        self.tasks
+            .iter()
+            .filter(|task| !task.is_divider() && filters.iter().all(|filter| filter(task)))
+            .count()
+    }
+
+    pub fn title(&self) -> String {
+        format!("taskt ({} pending)", self.pending_count())
+    }
+
+    pub fn first(&mut self) {
+        self.current_task = self.visible_indices().first().copied().unwrap_or(0);
+    }
+
+    pub fn last(&mut self) {
+        self.current_task = self.visible_indices().last().copied().unwrap_or(0);
+    }
+
+    pub fn autocapitalize(&self, text: String) -> String {
+        if !self.options.autocapitalize {
+            return text;
+        }
+
+        match text.char_indices().find(|(_, c)| !c.is_whitespace()) {
+            Some((index, c)) => format!(
+                "{}{}{}",
+                &text[..index],
+                c.to_uppercase(),
+                &text[index + c.len_utf8()..]
+            ),
+            None => text,
+        }
+    }
+
+    pub fn exceeds_max_length(&self, text: &str) -> bool {
+        match self.options.max_task_length {
+            Some(max) => text.chars().count() > max,
+            None => false,
+        }
+    }
+
+    pub fn fit_to_max_length(&self, text: String) -> Option<String> {
+        if !self.exceeds_max_length(&text) {
+            return Some(text);
+        }
+
+        match (
+            self.options.truncate_long_tasks,
+            self.options.max_task_length,
+        ) {
+            (true, Some(max)) => Some(text.chars().take(max).collect()),
+            _ => None,
+        }
+    }
+
+    pub fn prev(&mut self) {
+        let visible = self.visible_indices();
+
+        if let Some(position) = visible.iter().position(|&index| index == self.current_task) {
+            if let Some(&index) = visible[..position]
+                .iter()
+                .rev()
+                .find(|&&index| self.is_selectable(index))
+            {
+                self.current_task = index;
+            }
+        }
+    }
+
+    pub fn next(&mut self) {
+        let visible = self.visible_indices();
+
+        if let Some(position) = visible.iter().position(|&index| index == self.current_task) {
+            if let Some(&index) = visible[position + 1..]
+                .iter()
+                .find(|&&index| self.is_selectable(index))
+            {
+                self.current_task = index;
+            }
+        }
+    }
+}
+
+pub fn parse_filters(args: &[String]) -> Result<Vec<TaskFilter>, Error> {
+    let mut filters: Vec<TaskFilter> = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        filters.push(match arg.as_str() {
+            "--pending" => Box::new(|task| !task.is_done()),
+            "--done" => Box::new(Task::is_done),
+            "--todo" => Box::new(|task| task.status == TaskStatus::Todo),
+            "--waiting" => Box::new(|task| task.status == TaskStatus::Waiting),
+            "--estimated" => Box::new(|task| task.estimate_minutes.is_some()),
+            "--tag" => match args.next() {
+                Some(tag) => {
+                    let tag = tag.trim_start_matches('#').to_string();
+                    Box::new(move |task| task.has_tag(&tag))
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Usage: taskt count --tag <tag>",
+                    ))
+                }
+            },
+            "--overdue" => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "--overdue needs due dates, which tasks do not have yet",
+                ))
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown filter: {} (expected --pending, --done, --todo, --waiting, --estimated or --tag <tag>)",
+                        arg
+                    ),
+                ))
+            }
+        });
+    }
+
+    Ok(filters)
+}
+
+pub fn get_data_dir() -> Result<PathBuf, Error> {
+    let path = project_dirs()?.data_dir().to_path_buf();
+
+    fs::create_dir_all(&path)?;
+
+    Ok(path)
+}
+
+fn project_dirs() -> Result<ProjectDirs, Error> {
+    ProjectDirs::from("eu", "tortitas", "todot")
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "Unable to find the home directory"))
+}
+
+fn get_database_path(format: StorageFormat) -> Result<PathBuf, Error> {
+    Ok(get_data_dir()?.join(format.storage().file_name()))
+}
+
+fn completed_before(task: &Task, cutoff: u64) -> bool {
+    task.is_done()
+        && task
+            .completed_at
+            .is_some_and(|completed_at| completed_at <= cutoff)
+}
+
+fn split_archivable(tasks: &[Task], cutoff: u64) -> (Vec<Task>, Vec<Task>) {
+    tasks
+        .iter()
+        .cloned()
+        .partition(|task| completed_before(task, cutoff))
+}
+
+fn append_to_archive(path: &Path, tasks: &[Task]) -> Result<u64, Error> {
+    let mut data = Vec::new();
+
+    for task in tasks {
+        serde_json::to_writer(&mut data, task)?;
+        data.push(b'\n');
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let length = file.metadata()?.len();
+
+    if let Err(e) = file.write_all(&data) {
+        let _ = file.set_len(length);
+        return Err(e);
+    }
+
+    Ok(length)
+}
+
+fn truncate_archive(path: &Path, length: u64) -> Result<(), Error> {
+    fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(length)
+}
+
+fn get_ui_state_path() -> Result<PathBuf, Error> {
+    Ok(get_data_dir()?.join(UI_STATE_FILE))
+}
+
+fn get_pending_sync_path() -> Result<PathBuf, Error> {
+    Ok(get_data_dir()?.join(PENDING_SYNC_FILE))
+}
+
+fn get_lock_path() -> Result<PathBuf, Error> {
+    Ok(get_data_dir()?.join(LOCK_FILE))
+}
+
+fn write_lock(path: &Path) -> Result<(), Error> {
+    std::fs::write(path, format!("{}\n{}", std::process::id(), task::now()))
+}
+
+fn create_lock(path: &Path) -> Result<(), Error> {
+    let result = link_lock(path);
+
+    let stale = match &result {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => match fs::read_to_string(path) {
+            Ok(contents) => is_lock_stale(&contents),
+            Err(e) => e.kind() == ErrorKind::NotFound,
+        },
+        _ => false,
+    };
+
+    if !stale {
+        return result;
+    }
+
+    log::info!("Replacing stale lock file");
+    let _ = fs::remove_file(path);
+
+    link_lock(path)
+}
+
+fn link_lock(path: &Path) -> Result<(), Error> {
+    let staging = path.with_extension(format!("lock.{}", std::process::id()));
+
+    write_lock(&staging)?;
+    let linked = fs::hard_link(&staging, path);
+    let _ = fs::remove_file(&staging);
+
+    linked
+}
+
+fn is_lock_stale(contents: &str) -> bool {
+    let mut lines = contents.lines();
+
+    let pid = lines.next().and_then(|pid| pid.trim().parse::<u32>().ok());
+    let timestamp = lines
+        .next()
+        .and_then(|timestamp| timestamp.trim().parse::<u64>().ok());
+
+    let (pid, timestamp) = match (pid, timestamp) {
+        (Some(pid), Some(timestamp)) => (pid, timestamp),
+        _ => return true,
+    };
+
+    if cfg!(target_os = "linux") && !PathBuf::from(format!("/proc/{}", pid)).exists() {
+        return true;
+    }
+
+    task::now().saturating_sub(timestamp) > STALE_LOCK_AGE
+}
+
+fn read_local_tasks(format: StorageFormat) -> Result<Vec<Task>, Error> {
+    let data = match std::fs::read_to_string(get_database_path(format)?) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return read_other_format_tasks(format, e),
+        Err(e) => return Err(e),
+    };
+
+    format.storage().deserialize(&data)
+}
+
+fn read_other_format_tasks(format: StorageFormat, not_found: Error) -> Result<Vec<Task>, Error> {
+    for other in StorageFormat::ALL
+        .into_iter()
+        .filter(|&other| other != format)
+    {
+        if let Ok(data) = get_database_path(other).and_then(std::fs::read_to_string) {
+            log::info!(
+                "Converting {} to {}",
+                other.storage().file_name(),
+                format.storage().file_name()
+            );
+
+            return other.storage().deserialize(&data);
+        }
+    }
+
+    Err(not_found)
+}
+
+fn extract_url(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .map(|word| word.trim_start_matches(['(', '[', '<', '"', '\'']))
+        .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+        .map(|word| word.trim_end_matches(['.', ',', ';', ':', ')', ']', '>', '"', '\'']))
+}
+
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|q| text.any(|c| c == q))
+}
+
+fn colors_supported() -> bool {
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+
+    !matches!(std::env::var("TERM").as_deref(), Ok("dumb"))
+}
+
+fn status_marker(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Todo => "[ ]",
+        TaskStatus::Waiting => "[~]",
+        TaskStatus::Done => "[x]",
+    }
+}
+
+fn status_color(status: TaskStatus) -> Color {
+    match status {
+        TaskStatus::Todo => Color::Yellow,
+        TaskStatus::Waiting => Color::Cyan,
+        TaskStatus::Done => Color::Green,
+    }
+}
+
+fn emphasis_spans(text: &str) -> Vec<Span<'_>> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let start = match rest.find('*') {
+            Some(start) => start,
+            None => {
+                spans.push(Span::raw(rest));
+                break;
+            }
+        };
+
+        let (marker, modifier) = match rest[start..].starts_with("**") {
+            true => ("**", Modifier::BOLD),
+            false => ("*", Modifier::ITALIC),
+        };
+        let inner_start = start + marker.len();
+
+        match rest[inner_start..].find(marker) {
+            Some(length) if length > 0 => {
+                if start > 0 {
+                    spans.push(Span::raw(&rest[..start]));
+                }
+
+                spans.push(Span::styled(
+                    &rest[inner_start..inner_start + length],
+                    Style::default().add_modifier(modifier),
+                ));
+                rest = &rest[inner_start + length + marker.len()..];
+            }
+            _ => {
+                spans.push(Span::raw(&rest[..inner_start]));
+                rest = &rest[inner_start..];
+            }
+        }
+    }
+
+    spans
+}
+
+fn get_template_path(name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid template name: {}", name),
+        ));
+    }
+
+    let path = get_data_dir()?.join(TEMPLATES_DIR);
+
+    fs::create_dir_all(&path)?;
+
+    Ok(path.join(format!("{}.json", name)))
+}
+
+fn get_database_modified(format: StorageFormat) -> Option<SystemTime> {
+    get_database_path(format)
+        .and_then(fs::metadata)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn validate_server_address(server_address: &str) -> Result<(), String> {
+    let (host, port) = server_address
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected host:port, got \"{}\"", server_address))?;
+
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return Err(format!("invalid host \"{}\"", host));
+    }
+
+    if host.contains(':') && host.parse::<std::net::Ipv6Addr>().is_err() {
+        return Err(format!("invalid host \"{}\"", host));
+    }
+
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(()),
+        _ => Err(format!("invalid port \"{}\"", port)),
+    }
+}
+
+fn connect(server_address: &str, timeout: Duration) -> Result<TcpStream, Error> {
+    let address = server_address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Unable to resolve server address"))?;
+
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    Ok(stream)
+}
+
+fn send_tasks_to_server(todo: &Todo, server_address: &str) -> Result<(), Error> {
+    let mut input = String::from("write\n");
+
+    input.push_str(&serde_json::to_string(&todo.tasks)?);
+
+    let mut stream = connect(server_address, SYNC_TIMEOUT)?;
+
+    stream.write_all(input.as_bytes())?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut buffer: Vec<u8> = Vec::new();
+    reader.read_until(b'\n', &mut buffer)?;
+
+    Ok(())
+}
+
+fn read_tasks_from_server(server_address: String, timeout: Duration) -> Result<Vec<Task>, Error> {
+    let input = String::from("read\n");
+
+    let mut stream = connect(&server_address, timeout)?;
+
+    stream.write_all(input.as_bytes())?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut buffer: Vec<u8> = Vec::new();
+    reader.read_until(b'\n', &mut buffer)?;
+
+    let response = str::from_utf8(&buffer).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Server response is not valid UTF-8: {}", e),
+        )
+    })?;
+
+    serde_json::from_str(response).map_err(|e| e.into())
+}
+
+pub fn get_options() -> Options {
+    let mut options = read_options_file();
+
+    if let Ok(server_address) = std::env::var(SERVER_ENV_VAR) {
+        if !server_address.is_empty() {
+            options.server_address = Some(server_address);
+        }
+    }
+
+    options
+}
+
+fn read_options_file() -> Options {
+    let path = match project_dirs() {
+        Ok(dirs) => dirs.config_dir().to_path_buf(),
+        Err(e) => return Options::with_warning(format!("Unable to find config directory: {}", e)),
+    };
+
+    if let Err(e) = fs::create_dir_all(&path) {
+        return Options::with_warning(format!("Unable to create config directory: {}", e));
+    }
+
+    let path = path.join("config.toml");
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Options::default(),
+        Err(e) => return Options::with_warning(format!("Unable to read config file: {}", e)),
+    };
+
+    match toml::from_str(&contents) {
+        Ok(options) => options,
+        Err(e) => Options::with_warning(format!(
+            "Unable to parse config file, using defaults: {}",
+            e.message()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo_with(texts: &[&str]) -> Todo {
+        let mut todo = Todo::with_options(Options::default());
+        todo.read_only = true;
+        todo.tasks = texts
+            .iter()
+            .map(|text| Task::new(text.to_string()))
+            .collect();
+        todo
+    }
+
+    #[test]
+    fn accepts_valid_server_addresses() {
+        for address in ["localhost:7878", "10.0.0.2:1", "[::1]:65535"] {
+            assert_eq!(validate_server_address(address), Ok(()), "{}", address);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_server_addresses() {
+        for address in [
+            "localhost",
+            ":7878",
+            "my host:7878",
+            "[fe80::zz]:7878",
+            "localhost:0",
+            "localhost:65536",
+            "localhost:port",
+        ] {
+            assert!(validate_server_address(address).is_err(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn styles_bold_and_italic_spans() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let italic = Style::default().add_modifier(Modifier::ITALIC);
+
+        assert_eq!(
+            emphasis_spans("buy **milk** and *eggs* now"),
+            vec![
+                Span::raw("buy "),
+                Span::styled("milk", bold),
+                Span::raw(" and "),
+                Span::styled("eggs", italic),
+                Span::raw(" now"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_unmatched_markers_as_text() {
+        assert_eq!(
+            emphasis_spans("2 * 3"),
+            vec![Span::raw("2 *"), Span::raw(" 3")]
+        );
+        assert_eq!(
+            emphasis_spans("a **b"),
+            vec![Span::raw("a **"), Span::raw("b")]
+        );
+        assert_eq!(
+            emphasis_spans("****"),
+            vec![Span::raw("**"), Span::raw("**")]
+        );
+    }
+
+    #[test]
+    fn rejects_non_utf8_server_response() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            stream.write_all(b"\xff\xfe\n").unwrap();
+        });
+
+        let error = read_tasks_from_server(address, SYNC_TIMEOUT).err().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn clamps_selection_to_nearest_visible_task() {
+        let mut todo = todo_with(&["a", "b", "c", "d"]);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.tasks[2].set_status(TaskStatus::Done);
+        todo.current_task = 2;
+
+        todo.toggle_completed_collapsed();
+        assert_eq!(todo.current_task, 3);
+
+        todo.current_task = 1;
+        todo.clamp_selection_to_visible();
+        assert_eq!(todo.current_task, 0);
+    }
+
+    #[test]
+    fn clamps_selection_when_nothing_is_visible() {
+        let mut todo = todo_with(&["a"]);
+        todo.tasks[0].set_status(TaskStatus::Done);
+        todo.completed_collapsed = true;
+        todo.current_task = 5;
+
+        todo.clamp_selection_to_visible();
+        assert_eq!(todo.current_task, 0);
+    }
+
+    #[test]
+    fn fits_text_to_max_length() {
+        let mut todo = todo_with(&[]);
+        todo.options.max_task_length = Some(3);
+
+        assert_eq!(
+            todo.fit_to_max_length(String::from("abc")),
+            Some(String::from("abc"))
+        );
+        assert_eq!(todo.fit_to_max_length(String::from("abcd")), None);
+
+        todo.options.truncate_long_tasks = true;
+        assert_eq!(
+            todo.fit_to_max_length(String::from("ääää")),
+            Some(String::from("äää"))
+        );
+    }
+
+    #[test]
+    fn leaves_text_alone_without_max_length() {
+        let todo = todo_with(&[]);
+
+        assert_eq!(
+            todo.fit_to_max_length("a".repeat(500)),
+            Some("a".repeat(500))
+        );
+    }
+
+    #[test]
+    fn autocapitalizes_first_letter() {
+        let mut todo = todo_with(&[]);
+
+        assert_eq!(todo.autocapitalize(String::from("buy milk")), "buy milk");
+
+        todo.options.autocapitalize = true;
+        assert_eq!(todo.autocapitalize(String::from("buy milk")), "Buy milk");
+        assert_eq!(todo.autocapitalize(String::from("  élan")), "  Élan");
+        assert_eq!(todo.autocapitalize(String::from("ß")), "SS");
+        assert_eq!(todo.autocapitalize(String::from("1st")), "1st");
+        assert_eq!(todo.autocapitalize(String::from("   ")), "   ");
+    }
+
+    #[test]
+    fn dedup_merges_completion_into_first_copy() {
+        let mut todo = todo_with(&["a", "b", "a", "c"]);
+        todo.tasks[2].set_status(TaskStatus::Done);
+        todo.tasks[2].completed_at = Some(100);
+        todo.current_task = 3;
+
+        todo.dedup();
+
+        let texts: Vec<&str> = todo.tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["a", "b", "c"]);
+        assert!(todo.tasks[0].is_done());
+        assert_eq!(todo.tasks[0].completed_at, Some(100));
+        assert_eq!(todo.current_task, 2);
+    }
+
+    #[test]
+    fn dedup_keeps_same_text_with_different_kind_or_indent() {
+        let mut todo = todo_with(&["a", "a", "a"]);
+        todo.tasks[1].indent_level = 1;
+        todo.tasks[2].kind = TaskKind::Divider;
+
+        todo.dedup();
+
+        assert_eq!(todo.tasks.len(), 3);
+    }
+
+    #[test]
+    fn treats_malformed_or_old_locks_as_stale() {
+        let pid = std::process::id();
+
+        assert!(is_lock_stale(""));
+        assert!(is_lock_stale("not a pid\n1"));
+        assert!(is_lock_stale(&format!("{}\n", pid)));
+        assert!(is_lock_stale(&format!(
+            "{}\n{}",
+            pid,
+            task::now() - STALE_LOCK_AGE - 1
+        )));
+    }
+
+    #[test]
+    fn treats_recent_lock_of_running_process_as_held() {
+        let contents = format!("{}\n{}", std::process::id(), task::now());
+
+        assert!(!is_lock_stale(&contents));
+    }
+
+    #[test]
+    fn clamps_poll_interval() {
+        let mut options = Options::default();
+        assert_eq!(
+            options.poll_interval(),
+            Duration::from_millis(DEFAULT_POLL_INTERVAL_MS)
+        );
+
+        options.poll_interval_ms = Some(0);
+        assert_eq!(
+            options.poll_interval(),
+            Duration::from_millis(MIN_POLL_INTERVAL_MS)
+        );
+
+        options.poll_interval_ms = Some(1000);
+        assert_eq!(options.poll_interval(), Duration::from_millis(1000));
+
+        options.poll_interval_ms = Some(u64::MAX);
+        assert_eq!(
+            options.poll_interval(),
+            Duration::from_millis(MAX_POLL_INTERVAL_MS)
+        );
+    }
+
+    fn nested_todo() -> Todo {
+        let mut todo = todo_with(&["parent", "child", "grandchild", "sibling"]);
+        todo.tasks[1].indent_level = 1;
+        todo.tasks[2].indent_level = 2;
+        todo
+    }
+
+    #[test]
+    fn folding_hides_all_descendants() {
+        let mut todo = nested_todo();
+
+        todo.toggle_subtasks();
+
+        assert!(todo.tasks[0].collapsed);
+        assert!(!todo.is_folded_away(0));
+        assert!(todo.is_folded_away(1));
+        assert!(todo.is_folded_away(2));
+        assert!(!todo.is_folded_away(3));
+        assert_eq!(todo.visible_indices(), [0, 3]);
+
+        todo.next();
+        assert_eq!(todo.current_task, 3);
+        todo.prev();
+        assert_eq!(todo.current_task, 0);
+
+        todo.toggle_subtasks();
+        assert_eq!(todo.visible_indices(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn folding_ignores_tasks_without_subtasks() {
+        let mut todo = nested_todo();
+        todo.current_task = 3;
+
+        todo.toggle_subtasks();
+
+        assert!(!todo.tasks[3].collapsed);
+    }
+
+    #[test]
+    fn palette_jump_unfolds_ancestors() {
+        let mut todo = nested_todo();
+        todo.tasks[0].collapsed = true;
+        todo.tasks[1].collapsed = true;
+        todo.new_task_text = String::from("grandchild");
+
+        todo.jump_to_palette_selection();
+
+        assert_eq!(todo.current_task, 2);
+        assert!(!todo.tasks[0].collapsed);
+        assert!(!todo.tasks[1].collapsed);
+        assert!(todo.is_visible(2));
+    }
+
+    #[test]
+    fn archives_old_completed_tasks_and_keeps_the_rest_in_order() {
+        let mut todo = todo_with(&["old", "recent", "pending", "reopened", "older"]);
+        todo.tasks[0].set_status(TaskStatus::Done);
+        todo.tasks[0].completed_at = Some(100);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.tasks[1].completed_at = Some(300);
+        todo.tasks[3].set_status(TaskStatus::Done);
+        todo.tasks[3].set_status(TaskStatus::Todo);
+        todo.tasks[4].set_status(TaskStatus::Done);
+        todo.tasks[4].completed_at = Some(50);
+
+        let (archived, kept) = split_archivable(&todo.tasks, 200);
+
+        let texts = |tasks: &[Task]| -> Vec<String> {
+            tasks.iter().map(|task| task.text.clone()).collect()
+        };
+        assert_eq!(texts(&archived), ["old", "older"]);
+        assert_eq!(texts(&kept), ["recent", "pending", "reopened"]);
+    }
+
+    #[test]
+    fn sweeps_completed_tasks_only_after_delay() {
+        let mut task = Task::new(String::from("a"));
+        task.set_status(TaskStatus::Done);
+        let completed_at = task.completed_at.unwrap();
+
+        assert!(!completed_before(&task, completed_at - 1));
+        assert!(completed_before(&task, completed_at));
+
+        task.set_status(TaskStatus::Todo);
+        assert!(!completed_before(&task, completed_at + 60));
+    }
+
+    #[test]
+    fn indenting_under_folded_parent_keeps_selection_visible() {
+        let mut todo = nested_todo();
+        todo.tasks[0].collapsed = true;
+        todo.current_task = 3;
+
+        todo.indent();
+
+        assert!(todo.is_folded_away(3));
+        assert_eq!(todo.current_task, 0);
+
+        todo.next();
+        assert_eq!(todo.current_task, 0);
+        assert_eq!(todo.visible_indices(), [0]);
+    }
+
+    #[test]
+    fn outdenting_last_subtask_unfolds_parent() {
+        let mut todo = todo_with(&["parent", "child", "next"]);
+        todo.tasks[1].indent_level = 1;
+        todo.toggle_subtasks();
+        todo.current_task = 1;
+
+        assert!(todo.tasks[0].collapsed);
+
+        todo.outdent();
+
+        assert!(!todo.tasks[0].collapsed);
+        assert_eq!(todo.visible_indices(), [0, 1, 2]);
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("taskt-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn creates_lock_only_once() {
+        let path = scratch_dir("lock").join(LOCK_FILE);
+
+        create_lock(&path).unwrap();
+        assert!(!is_lock_stale(&fs::read_to_string(&path).unwrap()));
+
+        let error = create_lock(&path).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn replaces_stale_lock() {
+        let path = scratch_dir("stale-lock").join(LOCK_FILE);
+        fs::write(&path, "1\n0").unwrap();
+
+        create_lock(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(&format!("{}\n", std::process::id())));
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn extracts_first_url() {
+        assert_eq!(
+            extract_url("read https://example.com/a?b=1 and http://other.org"),
+            Some("https://example.com/a?b=1")
+        );
+        assert_eq!(
+            extract_url("see (https://example.com/docs)."),
+            Some("https://example.com/docs")
+        );
+        assert_eq!(
+            extract_url("quote \"http://example.com\","),
+            Some("http://example.com")
+        );
+        assert_eq!(
+            extract_url("<https://example.com/a.b>"),
+            Some("https://example.com/a.b")
+        );
+    }
+
+    #[test]
+    fn extracts_nothing_without_url() {
+        assert_eq!(extract_url("buy milk"), None);
+        assert_eq!(extract_url("ftp://example.com"), None);
+        assert_eq!(extract_url(""), None);
+    }
+
+    #[test]
+    fn normalizes_whitespace_on_save_when_enabled() {
+        let mut todo = todo_with(&["  buy   milk\t and  eggs  "]);
+
+        todo.save().unwrap();
+        assert_eq!(todo.tasks[0].text, "  buy   milk\t and  eggs  ");
+
+        todo.options.normalize_whitespace = true;
+        todo.save().unwrap();
+        assert_eq!(todo.tasks[0].text, "buy milk and eggs");
+    }
+
+    #[test]
+    fn exports_one_json_object_per_line() {
+        let mut todo = todo_with(&["buy milk", "line\nbreak", "call \"mom\""]);
+        todo.tasks[0].set_status(TaskStatus::Done);
+
+        let mut output = Vec::new();
+        todo.export_jsonl(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(output.ends_with('\n'));
+
+        let tasks: Vec<Task> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let texts: Vec<&str> = tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["buy milk", "line\nbreak", "call \"mom\""]);
+        assert!(tasks[0].is_done());
+        assert_eq!(tasks[0].completed_at, todo.tasks[0].completed_at);
+    }
+
+    #[test]
+    fn reloads_external_write_after_debounce() {
+        let mut todo = todo_with(&["a", "b"]);
+        todo.current_task = 1;
+        todo.dirty = true;
+
+        let start = Instant::now();
+        let written = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        let external = || {
+            Ok(vec![
+                Task::new(String::from("new")),
+                Task::new(String::from("b")),
+            ])
+        };
+
+        todo.reload_if_changed_with(written, start, || panic!("reloaded too early"));
+        assert!(todo.db_changed_at.is_some());
+
+        todo.reload_if_changed_with(written, start + RELOAD_DEBOUNCE / 2, || {
+            panic!("reloaded inside the debounce")
+        });
+
+        todo.reload_if_changed_with(written, start + RELOAD_DEBOUNCE, external);
+        let texts: Vec<&str> = todo.tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["new", "b"]);
+        assert_eq!(todo.current_task, 1);
+        assert!(!todo.dirty);
+        assert!(todo.db_changed_at.is_none());
+
+        todo.reload_if_changed_with(written, start + RELOAD_DEBOUNCE * 2, || {
+            panic!("reloaded an unchanged file")
+        });
+    }
+
+    #[test]
+    fn restarts_debounce_on_rapid_writes() {
+        let mut todo = todo_with(&["a"]);
+        let start = Instant::now();
+        let first = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        let second = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+
+        todo.reload_if_changed_with(first, start, || panic!("reloaded too early"));
+        todo.reload_if_changed_with(second, start + RELOAD_DEBOUNCE, || {
+            panic!("reloaded before the file settled")
+        });
+        todo.reload_if_changed_with(second, start + RELOAD_DEBOUNCE * 3 / 2, || {
+            panic!("reloaded inside the restarted debounce")
+        });
+
+        todo.reload_if_changed_with(second, start + RELOAD_DEBOUNCE * 2, || {
+            Ok(vec![Task::new(String::from("settled"))])
+        });
+        assert_eq!(todo.tasks[0].text, "settled");
+    }
+
+    #[test]
+    fn title_counts_pending_tasks() {
+        let mut todo = todo_with(&[]);
+        assert_eq!(todo.title(), "taskt (0 pending)");
+
+        todo.insert(String::from("a"));
+        todo.insert(String::from("b"));
+        assert_eq!(todo.title(), "taskt (2 pending)");
+
+        todo.tasks[0].set_status(TaskStatus::Waiting);
+        assert_eq!(todo.title(), "taskt (2 pending)");
+
+        todo.tasks[1].set_status(TaskStatus::Done);
+        assert_eq!(todo.title(), "taskt (1 pending)");
+    }
+
+    #[test]
+    fn dividers_are_not_counted() {
+        let mut todo = todo_with(&["── Morning ──", "a", "b"]);
+        todo.tasks[0].kind = TaskKind::Divider;
+        todo.tasks[2].set_status(TaskStatus::Done);
+
+        assert_eq!(todo.pending_count(), 1);
+        assert_eq!(todo.count_matching(&[]), 2);
+        assert_eq!(todo.title(), "taskt (1 pending)");
+
+        todo.current_task = 0;
+        todo.toggle();
+        assert!(!todo.tasks[0].is_done());
+        assert_eq!(todo.pending_count(), 1);
+    }
+
+    #[test]
+    fn toggle_visible_flips_only_visible_tasks() {
+        let mut todo = todo_with(&["a", "b", "c"]);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.completed_collapsed = true;
+
+        todo.toggle_visible();
+
+        assert!(todo.tasks[0].is_done());
+        assert!(todo.tasks[1].is_done());
+        assert!(todo.tasks[2].is_done());
+        assert!(todo.visible_indices().is_empty());
+
+        todo.completed_collapsed = false;
+        todo.tasks[0].collapsed = true;
+        todo.tasks[1].indent_level = 1;
+
+        todo.toggle_visible();
+
+        assert!(!todo.tasks[0].is_done());
+        assert!(todo.tasks[1].is_done());
+        assert!(!todo.tasks[2].is_done());
+    }
+
+    #[test]
+    fn split_replaces_task_with_pieces_at_its_index() {
+        let mut todo = todo_with(&["before", "pack and ship", "after"]);
+        todo.tasks[1].indent_level = 2;
+
+        todo.split(
+            1,
+            vec![String::from(" pack "), String::new(), String::from("ship")],
+        );
+
+        let texts: Vec<&str> = todo.tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["before", "pack", "ship", "after"]);
+        assert_eq!(todo.tasks[1].indent_level, 2);
+        assert_eq!(todo.tasks[2].indent_level, 2);
+        assert_eq!(todo.current_task, 1);
+    }
+
+    #[test]
+    fn split_ignores_empty_input_and_dividers() {
+        let mut todo = todo_with(&["a", "──"]);
+        todo.tasks[1].kind = TaskKind::Divider;
+
+        todo.split(0, vec![String::from("  ")]);
+        todo.split(1, vec![String::from("x"), String::from("y")]);
+
+        assert_eq!(todo.tasks.len(), 2);
+        assert_eq!(todo.tasks[0].text, "a");
+    }
+
+    #[test]
+    fn lists_recently_completed_newest_first() {
+        let texts: Vec<String> = (0..RECENTLY_DONE_LIMIT + 3)
+            .map(|index| format!("task {}", index))
+            .collect();
+        let mut todo = todo_with(&texts.iter().map(String::as_str).collect::<Vec<_>>());
+
+        for (index, task) in todo.tasks.iter_mut().enumerate().skip(1) {
+            task.set_status(TaskStatus::Done);
+            task.completed_at = Some(((index * 7) % 13) as u64);
+        }
+
+        let recent = todo.recently_completed();
+
+        assert_eq!(recent.len(), RECENTLY_DONE_LIMIT);
+        assert!(!recent.contains(&0));
+        assert!(recent
+            .windows(2)
+            .all(|pair| todo.tasks[pair[0]].completed_at >= todo.tasks[pair[1]].completed_at));
+        assert_eq!(todo.tasks[recent[0]].completed_at, Some(12));
+    }
+
+    #[test]
+    fn counts_completions_per_day() {
+        let mut todo = todo_with(&["today", "also today", "yesterday", "last month", "pending"]);
+        let now = task::now();
+
+        for (index, days_ago) in [0, 0, 1, 30].into_iter().enumerate() {
+            todo.tasks[index].set_status(TaskStatus::Done);
+            todo.tasks[index].completed_at = Some(now - days_ago * SECONDS_PER_DAY);
+        }
+
+        let per_day = todo.completions_per_day();
+
+        assert_eq!(per_day.len(), HISTOGRAM_DAYS as usize);
+        assert_eq!(per_day[6], (String::from("today"), 2));
+        assert_eq!(per_day[5], (String::from("-1d"), 1));
+        assert_eq!(per_day[0], (String::from("-6d"), 0));
+        assert_eq!(per_day.iter().map(|(_, count)| count).sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn successful_sync_updates_timestamp_and_failure_keeps_it() {
+        let mut todo = todo_with(&[]);
+        todo.options.show_last_sync = true;
+        todo.options.server_address = Some(String::from("localhost:7878"));
+        assert_eq!(todo.sync_status().as_deref(), Some("never synced"));
+
+        todo.mark_synced(false);
+        assert_eq!(todo.last_synced_at, None);
+        assert_eq!(todo.sync_status().as_deref(), Some("offline"));
+
+        todo.mark_synced(true);
+        let synced_at = todo.last_synced_at.unwrap();
+        assert!(synced_at + 1 >= task::now());
+        assert_eq!(todo.sync_status().as_deref(), Some("synced just now"));
+
+        todo.last_synced_at = Some(synced_at - 120);
+        todo.mark_synced(false);
+        assert_eq!(todo.last_synced_at, Some(synced_at - 120));
+        assert_eq!(
+            todo.sync_status().as_deref(),
+            Some("offline, synced 2m ago")
+        );
+    }
+
+    #[test]
+    fn round_trips_ui_state() {
+        let mut saved = todo_with(&["a", "b", "c"]);
+        saved.display_reversed = true;
+        saved.view_density = ViewDensity::Expanded;
+        saved.show_detail_pane = true;
+        saved.current_task = 2;
+
+        let data = serde_json::to_string(&saved.ui_state()).unwrap();
+
+        let mut restored = todo_with(&["b", "c"]);
+        restored.apply_ui_state(serde_json::from_str(&data).unwrap());
+
+        assert!(restored.display_reversed);
+        assert!(!restored.completed_collapsed);
+        assert!(restored.view_density == ViewDensity::Expanded);
+        assert!(restored.show_detail_pane);
+        assert_eq!(restored.current_task, 1);
+    }
+
+    #[test]
+    fn ignores_stale_ui_state_selection() {
+        let mut todo = todo_with(&["a", "b"]);
+        todo.tasks[0].set_status(TaskStatus::Done);
+
+        todo.apply_ui_state(
+            serde_json::from_str(r#"{"completed_collapsed":true,"selected_task":"gone"}"#).unwrap(),
+        );
+
+        assert!(todo.completed_collapsed);
+        assert_eq!(todo.current_task, 1);
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn counts_tasks_matching_all_filters() {
+        let mut todo = todo_with(&[
+            "#work report",
+            "#work call",
+            "groceries",
+            "#Work, deploy",
+            "──",
+        ]);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.tasks[2].estimate_minutes = Some(30);
+        todo.tasks[3].set_status(TaskStatus::Waiting);
+        todo.tasks[3].estimate_minutes = Some(10);
+        todo.tasks[4].kind = TaskKind::Divider;
+
+        let count = |flags: &[&str]| todo.count_matching(&parse_filters(&args(flags)).unwrap());
+
+        assert_eq!(count(&[]), 4);
+        assert_eq!(count(&["--pending"]), 3);
+        assert_eq!(count(&["--tag", "work"]), 3);
+        assert_eq!(count(&["--pending", "--tag", "#work"]), 2);
+        assert_eq!(count(&["--pending", "--tag", "work", "--estimated"]), 1);
+        assert_eq!(count(&["--todo", "--estimated"]), 1);
+        assert_eq!(count(&["--done", "--waiting"]), 0);
+    }
+
+    #[test]
+    fn rejects_unknown_or_incomplete_filters() {
+        for (flags, kind) in [
+            (&["--tag"][..], ErrorKind::InvalidInput),
+            (&["--pending", "--bogus"][..], ErrorKind::InvalidInput),
+            (&["--overdue"][..], ErrorKind::Unsupported),
+        ] {
+            assert_eq!(parse_filters(&args(flags)).err().unwrap().kind(), kind);
+        }
+    }
+
+    #[test]
+    fn inserts_below_and_above_selection() {
+        let mut todo = todo_with(&["a", "b", "c"]);
+        todo.current_task = 1;
+
+        let below = todo.neighbour_index(true);
+        todo.insert_at(below, String::from("below"));
+        assert_eq!(todo.current_task, 2);
+
+        let above = todo.neighbour_index(false);
+        todo.insert_at(above, String::from("above"));
+        assert_eq!(todo.current_task, 2);
+
+        let texts: Vec<&str> = todo.tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["a", "b", "above", "below", "c"]);
+    }
+
+    #[test]
+    fn inserts_relative_to_reversed_display() {
+        let mut todo = todo_with(&["a", "b"]);
+        todo.display_reversed = true;
+        todo.current_task = 1;
+
+        assert_eq!(todo.neighbour_index(true), 1);
+        assert_eq!(todo.neighbour_index(false), 2);
+        assert_eq!(todo_with(&[]).neighbour_index(true), 0);
+
+        todo.insert_at(99, String::from("end"));
+        assert_eq!(todo.current_task, 2);
+        assert_eq!(todo.tasks[2].text, "end");
+    }
+
+    #[test]
+    fn inserts_at_configured_position() {
+        let mut todo = todo_with(&["a", "b"]);
+
+        todo.insert(String::from("bottom"));
+        assert_eq!(todo.tasks[2].text, "bottom");
+        assert_eq!(todo.current_task, 2);
+
+        todo.options.insert_position = InsertPosition::Top;
+        todo.insert(String::from("top"));
+        assert_eq!(todo.tasks[0].text, "top");
+        assert_eq!(todo.current_task, 0);
+        assert_eq!(todo.tasks.len(), 4);
+    }
+
+    #[test]
+    fn jumps_to_first_and_last_task() {
+        let mut empty = todo_with(&[]);
+        empty.first();
+        assert_eq!(empty.current_task, 0);
+        empty.last();
+        assert_eq!(empty.current_task, 0);
+
+        let mut single = todo_with(&["a"]);
+        single.last();
+        assert_eq!(single.current_task, 0);
+        single.first();
+        assert_eq!(single.current_task, 0);
+
+        let mut todo = todo_with(&["a", "b", "c", "d"]);
+        todo.current_task = 1;
+        todo.last();
+        assert_eq!(todo.current_task, 3);
+        todo.first();
+        assert_eq!(todo.current_task, 0);
+
+        todo.tasks[0].set_status(TaskStatus::Done);
+        todo.tasks[3].set_status(TaskStatus::Done);
+        todo.completed_collapsed = true;
+        todo.first();
+        assert_eq!(todo.current_task, 1);
+        todo.last();
+        assert_eq!(todo.current_task, 2);
+    }
+
+    #[test]
+    fn numbers_visible_rows_with_aligned_indices() {
+        let texts: Vec<String> = (0..11).map(|index| index.to_string()).collect();
+        let mut todo = todo_with(&texts.iter().map(String::as_str).collect::<Vec<_>>());
+
+        assert!(todo
+            .row_prefixes()
+            .iter()
+            .all(|(_, prefix)| prefix.is_empty()));
+
+        todo.options.show_indices = true;
+        let prefixes = todo.row_prefixes();
+        assert_eq!(prefixes[0], (0, String::from(" 1. ")));
+        assert_eq!(prefixes[10], (10, String::from("11. ")));
+
+        todo.tasks[0].set_status(TaskStatus::Done);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.completed_collapsed = true;
+        let prefixes = todo.row_prefixes();
+        assert_eq!(prefixes.len(), 9);
+        assert_eq!(prefixes[0], (2, String::from("1. ")));
+        assert_eq!(prefixes[8], (10, String::from("9. ")));
+    }
+
+    #[test]
+    fn chooses_placeholder_for_empty_and_filtered_lists() {
+        let mut todo = todo_with(&[]);
+        assert_eq!(
+            todo.placeholder().as_deref(),
+            Some("No tasks — press i to add one")
+        );
+
+        todo.options.empty_message = Some(String::from("Nothing to do"));
+        assert_eq!(todo.placeholder().as_deref(), Some("Nothing to do"));
+
+        todo.tasks.push(Task::new(String::from("a")));
+        assert_eq!(todo.placeholder(), None);
+
+        todo.tasks[0].set_status(TaskStatus::Done);
+        todo.completed_collapsed = true;
+        assert_eq!(todo.placeholder().as_deref(), Some("No matches"));
+
+        todo.options.no_matches_message = Some(String::from("All done"));
+        assert_eq!(todo.placeholder().as_deref(), Some("All done"));
+    }
+
+    #[test]
+    fn reversing_display_keeps_stored_order() {
+        let mut todo = todo_with(&["a", "b", "c"]);
+
+        todo.toggle_display_reversed();
+
+        let texts: Vec<&str> = todo.tasks.iter().map(|task| task.text.as_str()).collect();
+        assert_eq!(texts, ["a", "b", "c"]);
+        assert_eq!(todo.visible_indices(), [2, 1, 0]);
+
+        todo.current_task = 2;
+        todo.next();
+        assert_eq!(todo.current_task, 1);
+        todo.toggle();
+        assert!(todo.tasks[1].is_done());
+        assert_eq!(todo.selected_position(), Some(1));
+
+        todo.toggle_display_reversed();
+        assert_eq!(todo.visible_indices(), [0, 1, 2]);
+        assert_eq!(todo.current_task, 1);
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> Result<usize, Error> {
+            Err(Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn serialization_errors_surface_as_errors() {
+        let todo = todo_with(&["a"]);
+
+        let error = todo.export_jsonl(FailingWriter).err().unwrap();
+
+        assert!(error.to_string().contains("disk full"));
+    }
+
+    #[test]
+    fn counts_pending_tasks_per_tag() {
+        let mut todo = todo_with(&[
+            "call mom #home #Phone",
+            "fix sink #home.",
+            "file taxes #work",
+            "buy milk",
+            "#home chores",
+        ]);
+        todo.tasks[1].set_status(TaskStatus::Done);
+        todo.tasks[2].set_status(TaskStatus::Done);
+        todo.tasks[4].kind = TaskKind::Divider;
+
+        assert_eq!(
+            todo.tag_counts(),
+            vec![
+                (String::from("home"), 1),
+                (String::from("phone"), 1),
+                (String::from("work"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_tag_filter_through_tags() {
+        let mut todo = todo_with(&["a #home", "b #work", "c"]);
+        todo.cycle_tag_filter();
+        assert_eq!(todo.tag_filter, None);
+
+        todo.toggle_tag_sidebar();
+        todo.cycle_tag_filter();
+        assert_eq!(todo.tag_filter.as_deref(), Some("home"));
+        assert_eq!(todo.visible_indices(), vec![0]);
+
+        todo.cycle_tag_filter();
+        assert_eq!(todo.tag_filter.as_deref(), Some("work"));
+        assert_eq!(todo.visible_indices(), vec![1]);
+
+        todo.cycle_tag_filter();
+        assert_eq!(todo.tag_filter, None);
+        assert_eq!(todo.visible_indices(), vec![0, 1, 2]);
+
+        todo.cycle_tag_filter();
+        todo.toggle_tag_sidebar();
+        assert_eq!(todo.tag_filter, None);
+    }
+
+    #[test]
+    fn groups_tasks_under_tag_headers() {
+        let mut todo = todo_with(&["a #work #home", "b", "c #home", "d #Work."]);
+        todo.options.group_by_tag = true;
+
+        assert_eq!(
+            todo.tag_groups(),
+            vec![
+                (String::from("#home"), vec![0, 2]),
+                (String::from("#work"), vec![0, 3]),
+                (String::from("(untagged)"), vec![1]),
+            ]
+        );
+
+        todo.options.group_by_first_tag = true;
+        assert_eq!(
+            todo.tag_groups(),
+            vec![
+                (String::from("#home"), vec![2]),
+                (String::from("#work"), vec![0, 3]),
+                (String::from("(untagged)"), vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn navigates_grouped_rows_skipping_headers() {
+        let mut todo = todo_with(&["a #work", "b", "c #home"]);
+        todo.options.group_by_tag = true;
+        todo.current_task = 2;
+
+        assert_eq!(todo.visible_indices(), vec![2, 0, 1]);
+        assert_eq!(todo.selected_position(), Some(1));
+
+        todo.next();
+        assert_eq!(todo.current_task, 0);
+        assert_eq!(todo.selected_position(), Some(3));
+
+        todo.next();
+        assert_eq!(todo.current_task, 1);
+        assert_eq!(todo.selected_position(), Some(5));
+        assert_eq!(todo.list().len(), 6);
+    }
 }